@@ -7,10 +7,8 @@ CREATE TABLE IF NOT EXISTS Users (
     email TEXT NOT NULL UNIQUE,
     last_seen TEXT NOT NULL,
     --UTC ISO8601 from Rust Crate=chrono, example : 2022-02-14T19:47:51.028632Z
-    age INTEGER CHECK (
-        age > 17
-        AND age < 128
-    ) NOT NULL,
+    birthdate TEXT CHECK (birthdate IS date(birthdate)) NOT NULL,
+    --ISO8601 date, example : 1990-02-14. Age is computed from it, users must be 18 at sign-up
     latitude REAL CHECK (
         latitude >= -90
         AND latitude <= 90
@@ -35,27 +33,126 @@ CREATE TABLE IF NOT EXISTS Users (
         looking_for_age_max > 17
         AND looking_for_age_max < 128
     ) NOT NULL DEFAULT 127,
-    description TEXT CHECK(LENGTH(description) <= 1000) DEFAULT ''
+    description TEXT CHECK(LENGTH(description) <= 1000) DEFAULT '',
+    -- passed users are shown again after a cooldown
+    recycle_passed_profiles INTEGER CHECK (recycle_passed_profiles IN (0, 1)) NOT NULL DEFAULT 1,
+    -- travel mode : temporary location the user discovers from and is discovered at, until travel_expiry
+    travel_latitude REAL,
+    travel_longitude REAL,
+    travel_label TEXT CHECK(LENGTH(travel_label) <= 100),
+    travel_expiry TEXT, --UTC ISO8601
+    -- optional fixed offset (km) applied to the stored locations, so that they are never the precise ones
+    location_offset_north REAL,
+    location_offset_east REAL,
+    location_updated_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z', --UTC ISO8601, locations changes are rate limited
+    -- optional profile details, lists are JSON arrays
+    interests TEXT CHECK (json_valid(interests)) NOT NULL DEFAULT '[]',
+    languages TEXT CHECK (json_valid(languages)) NOT NULL DEFAULT '[]',
+    height INTEGER CHECK (
+        height >= 100
+        AND height <= 250
+    ), --unit is centimeters
+    job TEXT CHECK(LENGTH(job) <= 100),
+    education TEXT CHECK(LENGTH(education) <= 100),
+    prompts TEXT CHECK (json_valid(prompts)) NOT NULL DEFAULT '[]', -- [{"question": "...", "answer": "..."}]
+    verified INTEGER CHECK (verified IN (0, 1)) NOT NULL DEFAULT 0 -- the user identity was checked
 );
 CREATE INDEX IF NOT EXISTS nomIndex ON Users(name);
+-- Users discovery locations (radians, travel location first) indexed as points, to prefilter discovery queries with a bounding box
+CREATE VIRTUAL TABLE IF NOT EXISTS UsersLocations USING rtree(
+    user_id, -- Users.user_id
+    min_latitude, max_latitude,
+    min_longitude, max_longitude
+);
+CREATE TRIGGER IF NOT EXISTS usersLocationsInsert AFTER INSERT ON Users BEGIN
+    INSERT OR REPLACE INTO UsersLocations VALUES (
+        new.user_id,
+        COALESCE(new.travel_latitude, new.latitude), COALESCE(new.travel_latitude, new.latitude),
+        COALESCE(new.travel_longitude, new.longitude), COALESCE(new.travel_longitude, new.longitude)
+    );
+END;
+CREATE TRIGGER IF NOT EXISTS usersLocationsUpdate AFTER UPDATE OF latitude, longitude, travel_latitude, travel_longitude ON Users BEGIN
+    INSERT OR REPLACE INTO UsersLocations VALUES (
+        new.user_id,
+        COALESCE(new.travel_latitude, new.latitude), COALESCE(new.travel_latitude, new.latitude),
+        COALESCE(new.travel_longitude, new.longitude), COALESCE(new.travel_longitude, new.longitude)
+    );
+END;
+CREATE TRIGGER IF NOT EXISTS usersLocationsDelete AFTER DELETE ON Users BEGIN
+    DELETE FROM UsersLocations WHERE user_id = old.user_id;
+END;
 CREATE TABLE IF NOT EXISTS Photos (
     photo_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
     photo_uuid BLOB NOT NULL,
     user_uuid BLOB NOT NULL,
-    url TEXT NOT NULL,
+    url TEXT NOT NULL, -- full variant
+    thumbnail_url TEXT NOT NULL,
+    card_url TEXT NOT NULL,
+    blurhash TEXT, -- placeholder while the photo loads
     display_order INTEGER CHECK (display_order IN (1, 2, 3, 4, 5, 6)) NOT NULL, -- 6 photos max
+    -- only approved photos are shown to other users, see service_layer::moderation_service
+    status TEXT CHECK (status IN ('pending', 'approved', 'rejected')) NOT NULL DEFAULT 'pending',
+    perceptual_hash INTEGER, -- 64 bits, see utilities::images::perceptual_hash
+    duplicate_of BLOB, -- photo_uuid of a near identical photo of another user, found on upload
     FOREIGN KEY(user_uuid) REFERENCES Users(user_uuid) ON DELETE CASCADE
 );
+CREATE UNIQUE INDEX IF NOT EXISTS photosOrderIndex ON Photos(user_uuid, display_order);
+CREATE INDEX IF NOT EXISTS photosStatusIndex ON Photos(status);
+-- Bands of the perceptual hash, 9 of them so that hashes PHOTO_DUPLICATE_DISTANCE bits apart or less
+-- share at least one, see photo_dal::get_duplicate_candidates
+CREATE INDEX IF NOT EXISTS photosHashBand0Index ON Photos((perceptual_hash >> 0) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand1Index ON Photos((perceptual_hash >> 7) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand2Index ON Photos((perceptual_hash >> 14) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand3Index ON Photos((perceptual_hash >> 21) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand4Index ON Photos((perceptual_hash >> 28) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand5Index ON Photos((perceptual_hash >> 35) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand6Index ON Photos((perceptual_hash >> 42) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand7Index ON Photos((perceptual_hash >> 49) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand8Index ON Photos((perceptual_hash >> 56) & 255);
+-- Photos whose objects are still to be deleted from the object store, see photos_service::delete_queued_photos
+CREATE TABLE IF NOT EXISTS PhotoDeletions (
+    deletion_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    photo_uuid BLOB NOT NULL UNIQUE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    --UTC ISO8601, retried later while the object store fails
+    next_attempt_datetime TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z',
+    last_error TEXT
+);
+CREATE TRIGGER IF NOT EXISTS usersPhotosDelete AFTER DELETE ON Users BEGIN
+    INSERT OR IGNORE INTO PhotoDeletions (photo_uuid) SELECT photo_uuid FROM Photos WHERE user_uuid = old.user_uuid;
+    DELETE FROM Photos WHERE user_uuid = old.user_uuid;
+END;
 CREATE TABLE IF NOT EXISTS MatchingResults (
     match_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
     match_uuid BLOB NOT NULL,
     swiper BLOB NOT NULL,
     swiped BLOB NOT NULL,
-    love INTEGER CHECK (love IN (0, 1)) NOT NULL,
+    love INTEGER CHECK (love IN (0, 1, 2)) NOT NULL, -- 0 : pass, 1 : like, 2 : super like
+    --UTC ISO8601 from Rust Crate=chrono, example : 2022-02-14T19:47:51.028632Z
+    swipe_datetime TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z',
+    -- 1 when a pass was shown again and swiped a second time, the swipe is then permanent
+    recycled INTEGER CHECK (recycled IN (0, 1)) NOT NULL DEFAULT 0,
+    -- 1 when the swipe was undone, the swiped user can then be swiped again
+    undone INTEGER CHECK (undone IN (0, 1)) NOT NULL DEFAULT 0,
     FOREIGN KEY(swiper) REFERENCES Users(user_uuid) ON DELETE CASCADE,
     FOREIGN KEY(swiped) REFERENCES Users(user_uuid) ON DELETE CASCADE,
     UNIQUE (swiper, swiped)
 );
+CREATE INDEX IF NOT EXISTS swiperIndex ON MatchingResults(swiper, swipe_datetime);
+-- Swipes received by a user, counted for every discovery candidate, see user_dal::find_love_targets
+CREATE INDEX IF NOT EXISTS swipedIndex ON MatchingResults(swiped, love, undone);
+CREATE TABLE IF NOT EXISTS SwipeUndos (
+    undo_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    undo_uuid BLOB NOT NULL,
+    user_uuid BLOB NOT NULL,
+    swiped BLOB NOT NULL,
+    --UTC ISO8601 from Rust Crate=chrono, example : 2022-02-14T19:47:51.028632Z
+    undo_datetime TEXT NOT NULL,
+    -- kind of the undone swipe, an undone super like still counts in the daily super like quota
+    love INTEGER CHECK (love IN (0, 1, 2)),
+    FOREIGN KEY(user_uuid) REFERENCES Users(user_uuid) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS swipeUndosIndex ON SwipeUndos(user_uuid, undo_datetime);
 CREATE TABLE IF NOT EXISTS Lovers (
     love_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
     love_uuid BLOB NOT NULL,
@@ -97,4 +194,22 @@ CREATE TABLE IF NOT EXISTS Feedbacks (
     feedback_message TEXT NOT NULL,
     creation_datetime TEXT NOT NULL,
     FOREIGN KEY(poster_uuid) REFERENCES Users(user_uuid)
-);
\ No newline at end of file
+);
+CREATE TABLE IF NOT EXISTS AccountFlags (
+    flag_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    flag_uuid BLOB NOT NULL,
+    user_uuid BLOB NOT NULL,
+    reason TEXT CHECK (reason IN ('swipe_cadence', 'like_ratio', 'off_deck_swipe', 'duplicate_photo')) NOT NULL,
+    occurrences INTEGER NOT NULL DEFAULT 1,
+    --UTC ISO8601 from Rust Crate=chrono, example : 2022-02-14T19:47:51.028632Z
+    last_flag_datetime TEXT NOT NULL,
+    FOREIGN KEY(user_uuid) REFERENCES Users(user_uuid) ON DELETE CASCADE,
+    UNIQUE (user_uuid, reason)
+);
+CREATE TABLE IF NOT EXISTS DiscoveryPreferences (
+    preference_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_uuid BLOB NOT NULL UNIQUE,
+    -- JSON, see requests::DiscoveryPreferences
+    preferences TEXT CHECK (json_valid(preferences)) NOT NULL,
+    FOREIGN KEY(user_uuid) REFERENCES Users(user_uuid) ON DELETE CASCADE
+);
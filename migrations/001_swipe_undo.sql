@@ -0,0 +1,12 @@
+-- Swipes are timestamped so that the last one can be undone
+ALTER TABLE MatchingResults ADD COLUMN swipe_datetime TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z';
+CREATE INDEX IF NOT EXISTS swiperIndex ON MatchingResults(swiper, swipe_datetime);
+CREATE TABLE IF NOT EXISTS SwipeUndos (
+    undo_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    undo_uuid BLOB NOT NULL,
+    user_uuid BLOB NOT NULL,
+    swiped BLOB NOT NULL,
+    --UTC ISO8601 from Rust Crate=chrono, example : 2022-02-14T19:47:51.028632Z
+    undo_datetime TEXT NOT NULL,
+    FOREIGN KEY(user_uuid) REFERENCES Users(user_uuid) ON DELETE CASCADE
+);
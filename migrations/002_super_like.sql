@@ -0,0 +1,20 @@
+-- MatchingResults.love can now be 2 (super like), SQLite can't alter a CHECK constraint so the table is rebuilt
+BEGIN TRANSACTION;
+CREATE TABLE MatchingResults_new (
+    match_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    match_uuid BLOB NOT NULL,
+    swiper BLOB NOT NULL,
+    swiped BLOB NOT NULL,
+    love INTEGER CHECK (love IN (0, 1, 2)) NOT NULL, -- 0 : pass, 1 : like, 2 : super like
+    --UTC ISO8601 from Rust Crate=chrono, example : 2022-02-14T19:47:51.028632Z
+    swipe_datetime TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z',
+    FOREIGN KEY(swiper) REFERENCES Users(user_uuid) ON DELETE CASCADE,
+    FOREIGN KEY(swiped) REFERENCES Users(user_uuid) ON DELETE CASCADE,
+    UNIQUE (swiper, swiped)
+);
+INSERT INTO MatchingResults_new (match_id, match_uuid, swiper, swiped, love, swipe_datetime)
+SELECT match_id, match_uuid, swiper, swiped, love, swipe_datetime FROM MatchingResults;
+DROP TABLE MatchingResults;
+ALTER TABLE MatchingResults_new RENAME TO MatchingResults;
+CREATE INDEX IF NOT EXISTS swiperIndex ON MatchingResults(swiper, swipe_datetime);
+COMMIT;
@@ -0,0 +1,12 @@
+-- Accounts flagged for review, for example when swiping like a bot
+CREATE TABLE IF NOT EXISTS AccountFlags (
+    flag_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    flag_uuid BLOB NOT NULL,
+    user_uuid BLOB NOT NULL,
+    reason TEXT CHECK (reason IN ('swipe_cadence', 'like_ratio', 'off_deck_swipe')) NOT NULL,
+    occurrences INTEGER NOT NULL DEFAULT 1,
+    --UTC ISO8601 from Rust Crate=chrono, example : 2022-02-14T19:47:51.028632Z
+    last_flag_datetime TEXT NOT NULL,
+    FOREIGN KEY(user_uuid) REFERENCES Users(user_uuid) ON DELETE CASCADE,
+    UNIQUE (user_uuid, reason)
+);
@@ -0,0 +1,3 @@
+-- Passed users can be shown again after a cooldown, once
+ALTER TABLE Users ADD COLUMN recycle_passed_profiles INTEGER CHECK (recycle_passed_profiles IN (0, 1)) NOT NULL DEFAULT 1;
+ALTER TABLE MatchingResults ADD COLUMN recycled INTEGER CHECK (recycled IN (0, 1)) NOT NULL DEFAULT 0;
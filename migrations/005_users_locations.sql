@@ -0,0 +1,17 @@
+-- Users locations (radians) indexed as points, to prefilter discovery queries with a bounding box
+CREATE VIRTUAL TABLE IF NOT EXISTS UsersLocations USING rtree(
+    user_id, -- Users.user_id
+    min_latitude, max_latitude,
+    min_longitude, max_longitude
+);
+CREATE TRIGGER IF NOT EXISTS usersLocationsInsert AFTER INSERT ON Users BEGIN
+    INSERT OR REPLACE INTO UsersLocations VALUES (new.user_id, new.latitude, new.latitude, new.longitude, new.longitude);
+END;
+CREATE TRIGGER IF NOT EXISTS usersLocationsUpdate AFTER UPDATE OF latitude, longitude ON Users BEGIN
+    INSERT OR REPLACE INTO UsersLocations VALUES (new.user_id, new.latitude, new.latitude, new.longitude, new.longitude);
+END;
+CREATE TRIGGER IF NOT EXISTS usersLocationsDelete AFTER DELETE ON Users BEGIN
+    DELETE FROM UsersLocations WHERE user_id = old.user_id;
+END;
+INSERT OR REPLACE INTO UsersLocations
+SELECT user_id, latitude, latitude, longitude, longitude FROM Users;
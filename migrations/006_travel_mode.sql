@@ -0,0 +1,21 @@
+-- Travel mode : a temporary discovery location, indexed instead of the home location while it lasts
+ALTER TABLE Users ADD COLUMN travel_latitude REAL;
+ALTER TABLE Users ADD COLUMN travel_longitude REAL;
+ALTER TABLE Users ADD COLUMN travel_label TEXT CHECK(LENGTH(travel_label) <= 100);
+ALTER TABLE Users ADD COLUMN travel_expiry TEXT;
+DROP TRIGGER IF EXISTS usersLocationsInsert;
+DROP TRIGGER IF EXISTS usersLocationsUpdate;
+CREATE TRIGGER IF NOT EXISTS usersLocationsInsert AFTER INSERT ON Users BEGIN
+    INSERT OR REPLACE INTO UsersLocations VALUES (
+        new.user_id,
+        COALESCE(new.travel_latitude, new.latitude), COALESCE(new.travel_latitude, new.latitude),
+        COALESCE(new.travel_longitude, new.longitude), COALESCE(new.travel_longitude, new.longitude)
+    );
+END;
+CREATE TRIGGER IF NOT EXISTS usersLocationsUpdate AFTER UPDATE OF latitude, longitude, travel_latitude, travel_longitude ON Users BEGIN
+    INSERT OR REPLACE INTO UsersLocations VALUES (
+        new.user_id,
+        COALESCE(new.travel_latitude, new.latitude), COALESCE(new.travel_latitude, new.latitude),
+        COALESCE(new.travel_longitude, new.longitude), COALESCE(new.travel_longitude, new.longitude)
+    );
+END;
@@ -0,0 +1,4 @@
+-- Optional fixed offset on stored locations, and rate limiting of location changes
+ALTER TABLE Users ADD COLUMN location_offset_north REAL;
+ALTER TABLE Users ADD COLUMN location_offset_east REAL;
+ALTER TABLE Users ADD COLUMN location_updated_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z';
@@ -0,0 +1,5 @@
+-- Age is computed from the birthdate. Existing users get a birthdate matching their current age,
+-- with their next birthday half a year from now
+ALTER TABLE Users ADD COLUMN birthdate TEXT CHECK (birthdate IS date(birthdate)) NOT NULL DEFAULT '1970-01-01';
+UPDATE Users SET birthdate = date('now', '-' || age || ' years', '-6 months');
+ALTER TABLE Users DROP COLUMN age;
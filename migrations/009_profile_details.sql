@@ -0,0 +1,7 @@
+-- Optional profile details, lists are JSON arrays
+ALTER TABLE Users ADD COLUMN interests TEXT CHECK (json_valid(interests)) NOT NULL DEFAULT '[]';
+ALTER TABLE Users ADD COLUMN languages TEXT CHECK (json_valid(languages)) NOT NULL DEFAULT '[]';
+ALTER TABLE Users ADD COLUMN height INTEGER CHECK (height >= 100 AND height <= 250);
+ALTER TABLE Users ADD COLUMN job TEXT CHECK(LENGTH(job) <= 100);
+ALTER TABLE Users ADD COLUMN education TEXT CHECK(LENGTH(education) <= 100);
+ALTER TABLE Users ADD COLUMN prompts TEXT CHECK (json_valid(prompts)) NOT NULL DEFAULT '[]';
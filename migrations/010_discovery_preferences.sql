@@ -0,0 +1,9 @@
+-- Discovery preferences, and verified users that they can filter on
+ALTER TABLE Users ADD COLUMN verified INTEGER CHECK (verified IN (0, 1)) NOT NULL DEFAULT 0;
+CREATE TABLE IF NOT EXISTS DiscoveryPreferences (
+    preference_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    user_uuid BLOB NOT NULL UNIQUE,
+    -- JSON, see requests::DiscoveryPreferences
+    preferences TEXT CHECK (json_valid(preferences)) NOT NULL,
+    FOREIGN KEY(user_uuid) REFERENCES Users(user_uuid) ON DELETE CASCADE
+);
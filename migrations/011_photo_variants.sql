@@ -0,0 +1,5 @@
+-- Photos are stored as thumbnail, card and full variants, url being the full one.
+-- Photos uploaded before only have their original, used for every variant
+ALTER TABLE Photos ADD COLUMN thumbnail_url TEXT NOT NULL DEFAULT '';
+ALTER TABLE Photos ADD COLUMN card_url TEXT NOT NULL DEFAULT '';
+UPDATE Photos SET thumbnail_url = url, card_url = url;
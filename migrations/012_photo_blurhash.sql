@@ -0,0 +1,2 @@
+-- Placeholder shown while the photo loads, computed by the reprocess-photos command for existing photos
+ALTER TABLE Photos ADD COLUMN blurhash TEXT;
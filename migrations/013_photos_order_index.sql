@@ -0,0 +1,11 @@
+-- Number the photos of each user 1, 2, 3... in their current order, removing gaps and duplicates
+-- left by interrupted deletions or switches, then make the order unique per user
+UPDATE Photos SET display_order = (
+    SELECT COUNT(*) FROM Photos AS Previous
+    WHERE Previous.user_uuid = Photos.user_uuid
+    AND (
+        Previous.display_order < Photos.display_order
+        OR (Previous.display_order = Photos.display_order AND Previous.photo_id <= Photos.photo_id)
+    )
+);
+CREATE UNIQUE INDEX IF NOT EXISTS photosOrderIndex ON Photos(user_uuid, display_order);
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS PhotoDeletions (
+    deletion_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    photo_uuid BLOB NOT NULL UNIQUE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    --UTC ISO8601, retried later while the object store fails
+    next_attempt_datetime TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z',
+    last_error TEXT
+);
+CREATE TRIGGER IF NOT EXISTS usersPhotosDelete AFTER DELETE ON Users BEGIN
+    INSERT OR IGNORE INTO PhotoDeletions (photo_uuid) SELECT photo_uuid FROM Photos WHERE user_uuid = old.user_uuid;
+    DELETE FROM Photos WHERE user_uuid = old.user_uuid;
+END;
+-- Foreign keys are not enforced, the photos of users deleted so far are still there
+INSERT OR IGNORE INTO PhotoDeletions (photo_uuid)
+    SELECT photo_uuid FROM Photos WHERE user_uuid NOT IN (SELECT user_uuid FROM Users);
+DELETE FROM Photos WHERE user_uuid NOT IN (SELECT user_uuid FROM Users);
@@ -0,0 +1,4 @@
+ALTER TABLE Photos ADD COLUMN status TEXT CHECK (status IN ('pending', 'approved', 'rejected')) NOT NULL DEFAULT 'pending';
+-- Photos uploaded so far went live without moderation
+UPDATE Photos SET status = 'approved';
+CREATE INDEX IF NOT EXISTS photosStatusIndex ON Photos(status);
@@ -0,0 +1,19 @@
+ALTER TABLE Photos ADD COLUMN perceptual_hash INTEGER; -- 64 bits, see utilities::images::perceptual_hash
+ALTER TABLE Photos ADD COLUMN duplicate_of BLOB; -- photo_uuid of a near identical photo of another user, found on upload
+-- A CHECK can't be altered, AccountFlags is rebuilt to accept the duplicate_photo reason
+BEGIN;
+CREATE TABLE AccountFlagsNew (
+    flag_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    flag_uuid BLOB NOT NULL,
+    user_uuid BLOB NOT NULL,
+    reason TEXT CHECK (reason IN ('swipe_cadence', 'like_ratio', 'off_deck_swipe', 'duplicate_photo')) NOT NULL,
+    occurrences INTEGER NOT NULL DEFAULT 1,
+    --UTC ISO8601 from Rust Crate=chrono, example : 2022-02-14T19:47:51.028632Z
+    last_flag_datetime TEXT NOT NULL,
+    FOREIGN KEY(user_uuid) REFERENCES Users(user_uuid) ON DELETE CASCADE,
+    UNIQUE (user_uuid, reason)
+);
+INSERT INTO AccountFlagsNew SELECT * FROM AccountFlags;
+DROP TABLE AccountFlags;
+ALTER TABLE AccountFlagsNew RENAME TO AccountFlags;
+COMMIT;
@@ -0,0 +1,3 @@
+-- An undone swipe is kept, so that it still uses its daily slot and a recycled pass stays recycled
+ALTER TABLE MatchingResults ADD COLUMN undone INTEGER CHECK (undone IN (0, 1)) NOT NULL DEFAULT 0;
+CREATE INDEX IF NOT EXISTS swipeUndosIndex ON SwipeUndos(user_uuid, undo_datetime);
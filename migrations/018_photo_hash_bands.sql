@@ -0,0 +1,11 @@
+-- Bands of the perceptual hash, 9 of them so that hashes PHOTO_DUPLICATE_DISTANCE bits apart or less
+-- share at least one, see photo_dal::get_duplicate_candidates
+CREATE INDEX IF NOT EXISTS photosHashBand0Index ON Photos((perceptual_hash >> 0) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand1Index ON Photos((perceptual_hash >> 7) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand2Index ON Photos((perceptual_hash >> 14) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand3Index ON Photos((perceptual_hash >> 21) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand4Index ON Photos((perceptual_hash >> 28) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand5Index ON Photos((perceptual_hash >> 35) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand6Index ON Photos((perceptual_hash >> 42) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand7Index ON Photos((perceptual_hash >> 49) & 127);
+CREATE INDEX IF NOT EXISTS photosHashBand8Index ON Photos((perceptual_hash >> 56) & 255);
@@ -0,0 +1,2 @@
+-- Swipes received by a user, counted for every discovery candidate, see user_dal::find_love_targets
+CREATE INDEX IF NOT EXISTS swipedIndex ON MatchingResults(swiped, love, undone);
@@ -0,0 +1,2 @@
+-- Kind of the undone swipe, an undone super like still counts in the daily super like quota
+ALTER TABLE SwipeUndos ADD COLUMN love INTEGER CHECK (love IN (0, 1, 2));
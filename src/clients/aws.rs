@@ -1,16 +1,15 @@
+use crate::clients::object_store::{ObjectInfo, ObjectStore};
+use crate::my_errors::store_errors::StoreError;
 use aws_sdk_s3::error::SdkError;
-use aws_sdk_s3::operation::{
-    delete_object::{DeleteObjectError, DeleteObjectOutput},
-    put_object::{PutObjectError, PutObjectOutput},
-};
+use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_s3::Client;
-use aws_smithy_http;
 use aws_types::region::Region;
+use axum::async_trait;
 
 pub struct AwsClient {
     client: Client,
     bucket_name: String,
-    pub r2_image_domain: String,
+    r2_image_domain: String,
 }
 
 impl AwsClient {
@@ -30,36 +29,105 @@ impl AwsClient {
             .await;
         AwsClient {
             client: Client::new(&shared_config),
-            bucket_name: bucket_name,
-            r2_image_domain: r2_image_domain,
+            bucket_name,
+            r2_image_domain,
         }
     }
+}
+
+fn map_sdk_error<E: std::fmt::Debug, R: std::fmt::Debug>(e: SdkError<E, R>) -> StoreError {
+    StoreError::Backend(format!("{:?}", e))
+}
 
-    pub async fn upload_object(
-        &self,
-        key: &str,
-        content_type: &str,
-        data: aws_smithy_http::byte_stream::ByteStream,
-    ) -> Result<PutObjectOutput, SdkError<PutObjectError>> {
+#[async_trait]
+impl ObjectStore for AwsClient {
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), StoreError> {
         self.client
             .put_object()
             .bucket(&self.bucket_name)
             .key(key)
-            .body(data)
+            .body(ByteStream::from(data))
             .content_type(content_type)
             .send()
             .await
+            .map_err(map_sdk_error)?;
+        Ok(())
     }
 
-    pub async fn delete_object(
-        &self,
-        key: &str,
-    ) -> Result<DeleteObjectOutput, SdkError<DeleteObjectError>> {
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket_name)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| match e.into_service_error() {
+                e if e.is_no_such_key() => StoreError::NotFound,
+                e => StoreError::Backend(format!("{:?}", e)),
+            })?;
+        let data = object
+            .body
+            .collect()
+            .await
+            .map_err(|e| StoreError::Backend(format!("{:?}", e)))?;
+        Ok(data.into_bytes().to_vec())
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StoreError> {
+        let object = self
+            .client
+            .head_object()
+            .bucket(&self.bucket_name)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| match e.into_service_error() {
+                e if e.is_not_found() => StoreError::NotFound,
+                e => StoreError::Backend(format!("{:?}", e)),
+            })?;
+        Ok(ObjectInfo {
+            size: object.content_length().max(0) as u64,
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
         self.client
             .delete_object()
             .bucket(&self.bucket_name)
             .key(key)
             .send()
             .await
+            .map_err(map_sdk_error)?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        let mut keys = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let page = self
+                .client
+                .list_objects_v2()
+                .bucket(&self.bucket_name)
+                .prefix(prefix)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(map_sdk_error)?;
+            for object in page.contents().unwrap_or_default() {
+                if let Some(key) = object.key() {
+                    keys.push(key.to_string());
+                }
+            }
+            if !page.is_truncated() {
+                return Ok(keys);
+            }
+            continuation_token = page.next_continuation_token().map(String::from);
+        }
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("{}{}", self.r2_image_domain, key)
     }
 }
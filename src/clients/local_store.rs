@@ -0,0 +1,86 @@
+use crate::clients::object_store::{ObjectInfo, ObjectStore};
+use crate::my_errors::store_errors::StoreError;
+use axum::async_trait;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+// Objects are files of `directory`, served by the server itself under LOCAL_STORAGE_ROUTE.
+// Meant for development, the content type is not kept.
+pub struct LocalStore {
+    directory: PathBuf,
+    public_url: String,
+}
+
+impl LocalStore {
+    pub fn new(directory: String, public_url: String) -> LocalStore {
+        std::fs::create_dir_all(&directory).expect("failed to create the local storage directory");
+        LocalStore {
+            directory: PathBuf::from(directory),
+            public_url,
+        }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StoreError> {
+        // Keys must stay inside the storage directory
+        if key.is_empty() || key.contains('/') || key.contains('\\') || key.contains("..") {
+            return Err(StoreError::InvalidKey);
+        }
+        Ok(self.directory.join(key))
+    }
+}
+
+fn map_io_error(e: std::io::Error) -> StoreError {
+    match e.kind() {
+        ErrorKind::NotFound => StoreError::NotFound,
+        _ => StoreError::Backend(e.to_string()),
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LocalStore {
+    async fn put(&self, key: &str, _: &str, data: Vec<u8>) -> Result<(), StoreError> {
+        tokio::fs::write(self.path(key)?, data)
+            .await
+            .map_err(map_io_error)
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+        tokio::fs::read(self.path(key)?).await.map_err(map_io_error)
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StoreError> {
+        let metadata = tokio::fs::metadata(self.path(key)?)
+            .await
+            .map_err(map_io_error)?;
+        Ok(ObjectInfo {
+            size: metadata.len(),
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(map_io_error(e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        let mut entries = tokio::fs::read_dir(&self.directory)
+            .await
+            .map_err(map_io_error)?;
+        let mut keys = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(map_io_error)? {
+            if let Some(key) = entry.file_name().to_str() {
+                if key.starts_with(prefix) {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("{}{}", self.public_url, key)
+    }
+}
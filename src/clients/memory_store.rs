@@ -0,0 +1,59 @@
+use crate::clients::object_store::{ObjectInfo, ObjectStore};
+use crate::my_errors::store_errors::StoreError;
+use axum::async_trait;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Objects only live as long as the server, for tests. They are not served.
+#[derive(Default)]
+pub struct MemoryStore {
+    objects: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+#[async_trait]
+impl ObjectStore for MemoryStore {
+    async fn put(&self, key: &str, _: &str, data: Vec<u8>) -> Result<(), StoreError> {
+        self.objects.lock().unwrap().insert(key.to_string(), data);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+        self.objects
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .ok_or(StoreError::NotFound)
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StoreError> {
+        self.objects
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|data| ObjectInfo {
+                size: data.len() as u64,
+            })
+            .ok_or(StoreError::NotFound)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        self.objects.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StoreError> {
+        Ok(self
+            .objects
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("memory://{}", key)
+    }
+}
@@ -1 +1,4 @@
 pub mod aws;
+pub mod local_store;
+pub mod memory_store;
+pub mod object_store;
@@ -0,0 +1,40 @@
+use crate::clients::aws::AwsClient;
+use crate::clients::local_store::LocalStore;
+use crate::clients::memory_store::MemoryStore;
+use crate::configs::config::{Config, StorageBackend};
+use crate::my_errors::store_errors::StoreError;
+use axum::async_trait;
+
+pub struct ObjectInfo {
+    pub size: u64, // bytes
+}
+
+// Where photos are stored. Objects are public, at public_url(key).
+// Deleting an object that does not exist is not an error.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), StoreError>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>, StoreError>;
+    async fn head(&self, key: &str) -> Result<ObjectInfo, StoreError>;
+    async fn delete(&self, key: &str) -> Result<(), StoreError>;
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, StoreError>; // keys starting with prefix
+    fn public_url(&self, key: &str) -> String;
+}
+
+pub async fn from_config(config: &Config) -> Box<dyn ObjectStore> {
+    match config.storage.backend {
+        StorageBackend::R2 => Box::new(
+            AwsClient::new(
+                config.r2_account_id.clone(),
+                config.r2_image_domain.clone(),
+                config.bucket_name.clone(),
+            )
+            .await,
+        ),
+        StorageBackend::Local => Box::new(LocalStore::new(
+            config.storage.local_directory.clone(),
+            config.storage.local_public_url.clone(),
+        )),
+        StorageBackend::Memory => Box::<MemoryStore>::default(),
+    }
+}
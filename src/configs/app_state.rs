@@ -1,25 +1,43 @@
-use crate::clients;
-use crate::configs::config::Config;
-use crate::constants::constants::DATABASE_NAME;
+use crate::clients::object_store;
+use crate::clients::object_store::ObjectStore;
+use crate::configs::config::{Config, ModerationConfig, SwipeConfig};
+use crate::constants::constants::{DATABASE_NAME, PHOTO_PROCESSING_PERMITS};
+use crate::service_layer::discovery_service::ServedDeck;
+use crate::service_layer::moderation_service;
+use crate::service_layer::moderation_service::PhotoModerator;
+use crate::service_layer::ranking_service::Ranker;
 use crate::service_layer::sse_service::SseMessage;
 use r2d2::Pool;
 
 use r2d2_sqlite::SqliteConnectionManager;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, Semaphore};
 
 pub struct AppState {
     pub connection: Pool<SqliteConnectionManager>,
     pub txs: Mutex<HashMap<String, broadcast::Sender<SseMessage>>>,
-    pub aws_client: clients::aws::AwsClient,
+    pub decks: Mutex<HashMap<String, ServedDeck>>,
+    pub object_store: Box<dyn ObjectStore>,
     pub key_jwt: String,
     pub refresh_key_jwt: String,
+    pub mutual_discovery: bool,
+    pub likes_received_visible: usize,
+    pub likes_received_blur: bool,
+    pub ranker: Ranker,
+    pub candidate_pool: usize,
+    pub swipe_config: SwipeConfig,
+    pub photo_moderator: Box<dyn PhotoModerator>,
+    pub moderation_config: ModerationConfig,
+    pub photo_permits: Semaphore, // bounds the memory taken by decoded photos, see photos_service
 }
 
 impl AppState {
     pub async fn new(config: &Config) -> Arc<AppState> {
-        let manager = SqliteConnectionManager::file(DATABASE_NAME);
+        AppState::with_database(config, SqliteConnectionManager::file(DATABASE_NAME)).await
+    }
+
+    pub async fn with_database(config: &Config, manager: SqliteConnectionManager) -> Arc<AppState> {
         let pool = r2d2::Pool::builder()
             .max_size(100)
             .build(manager)
@@ -48,18 +66,23 @@ impl AppState {
         println!("pragma 2 {:?}", pragma2);
         println!("pragma 3 {:?}", pragma3);
         // println!("pragma 4 {:?}", pragma4);
-        let aws_client = clients::aws::AwsClient::new(
-            config.r2_account_id.clone(),
-            config.r2_image_domain.clone(),
-            config.bucket_name.clone(),
-        )
-        .await;
+        let object_store = object_store::from_config(config).await;
         Arc::new(AppState {
             connection: pool,
             txs: Mutex::new(HashMap::new()),
-            aws_client: aws_client,
+            decks: Mutex::new(HashMap::new()),
+            object_store,
             key_jwt: config.key_jwt.clone(),
             refresh_key_jwt: config.refresh_key_jwt.clone(),
+            mutual_discovery: config.mutual_discovery,
+            likes_received_visible: config.likes_received_visible,
+            likes_received_blur: config.likes_received_blur,
+            ranker: Ranker::new(&config.ranking),
+            candidate_pool: config.ranking.candidate_pool,
+            swipe_config: config.swipes.clone(),
+            photo_moderator: moderation_service::from_config(&config.moderation),
+            moderation_config: config.moderation.clone(),
+            photo_permits: Semaphore::new(PHOTO_PROCESSING_PERMITS),
         })
     }
 }
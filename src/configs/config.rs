@@ -11,6 +11,29 @@ pub struct Config {
     pub wed_domains: Vec<String>,
     pub r2_account_id: String,
     pub r2_image_domain: String,
+    pub mutual_discovery: bool, // false : only the searching user preferences are used to filter users
+    pub likes_received_visible: usize, // how many of the most recent likes received are shown in clear
+    pub likes_received_blur: bool, // false : likes past likes_received_visible are not sent at all
+    pub ranking: RankingConfig,
+    pub swipes: SwipeConfig,
+    pub storage: StorageConfig,
+    pub moderation: ModerationConfig,
+}
+
+// Weights of the discovery ranking strategies, a weight of 0 disables the strategy
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RankingConfig {
+    pub seed: u64,
+    pub candidate_pool: usize, // how many of the most recently active users are ranked
+    pub jitter: f32,
+    pub recency: f32,
+    pub distance: f32,
+    pub attractiveness: f32,
+    pub liked_you: f32,
+    pub super_liked_you: f32,
+    pub new_user: f32,
+    pub compatibility: f32,
+    pub soft_preferences: f32, // how much the soft discovery preferences rank down the users not matching them
 }
 
 impl Config {
@@ -21,6 +44,50 @@ impl Config {
         let filename = "src/configs/prod.toml";
 
         let file_content = fs::read_to_string(filename).expect("failed to read toml config");
-        return toml::from_str(&file_content).expect("failed to parse string file into toml");
+        toml::from_str(&file_content).expect("failed to parse string file into toml")
     }
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SwipeConfig {
+    pub daily_quota: usize,           // swipes per rolling 24 hours
+    pub max_swipes_per_second: usize, // above this the account is flagged for review
+    pub like_ratio_sample: usize, // the account is flagged for review if its last swipes of this sample are all likes
+    pub undo_window: i64,         // seconds, only a swipe more recent than this can be undone
+    pub undo_daily_quota: usize,
+    pub undo_tears_down_match: bool, // false : a swipe that created a match cannot be undone
+    pub super_like_daily_quota: usize,
+    pub pass_cooldown_days: u32, // a passed user is shown again after this, only once
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    pub local_directory: String, // local backend only, files served under LOCAL_STORAGE_ROUTE
+    pub local_public_url: String, // local backend only, prefix of the photo urls
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum StorageBackend {
+    #[serde(rename = "r2")]
+    R2,
+    #[serde(rename = "local")]
+    Local,
+    #[serde(rename = "memory")]
+    Memory, // photos are lost on restart and not served, for tests
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModerationConfig {
+    pub moderator: ModeratorKind,
+    pub require_approved_photo: bool, // true : users without an approved photo are left out of discovery
+    pub admins: Vec<String>,          // uuids of the users allowed to review photos
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ModeratorKind {
+    #[serde(rename = "none")]
+    None, // photos are approved on upload
+    #[serde(rename = "rules")]
+    Rules, // see moderation_service::RulesModerator
+}
@@ -10,3 +10,90 @@ pub const DATABASE_NAME: &str = "love.db";
 pub const TOKEN_LIFESPAN: usize = 3600; // seconds
 pub const TOKEN_REFRESH_LIFESPAN: usize = 3600 * 24 * 2; // seconds
 pub const DEFAULT_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$SZZVht0nCXacXAJU1dYJ8w$QwpNt6gUQ2K+dHQVDTf5H1mkkA0yTkXXKwZ6vHkKClQ";
+
+pub const DECK_CARD_TTL: u64 = 60 * 30; // seconds, a served card that is not swiped can be served again after this
+pub const DECK_DEFAULT_SIZE: usize = 10;
+pub const DECK_MAX_SIZE: usize = 50;
+pub const NEW_USER_BOOST_DAYS: f32 = 7.; // users created less than this ago are boosted by the new user ranking
+pub const COMPATIBILITY_INTERESTS: f32 = 0.4; // shares of each part in the compatibility score
+pub const COMPATIBILITY_AGE: f32 = 0.2;
+pub const COMPATIBILITY_DISTANCE: f32 = 0.2;
+pub const COMPATIBILITY_RECENCY: f32 = 0.2;
+pub const AGE_GAP_HALF_SCORE: f32 = 5.; // years, an age gap this large halves the age part of the compatibility
+pub const LIKES_PAGE_DEFAULT_SIZE: usize = 20;
+pub const LIKES_PAGE_MAX_SIZE: usize = 50;
+pub const PENDING_PHOTOS_PAGE_DEFAULT_SIZE: usize = 50;
+pub const PENDING_PHOTOS_PAGE_MAX_SIZE: usize = 200;
+pub const TRAVEL_MAX_DAYS: i64 = 30;
+pub const TRAVEL_LABEL_MAX_LENGTH: usize = 100;
+pub const TRAVEL_EXPIRY_SWEEP_INTERVAL: u64 = 60; // seconds
+pub const LOCATION_FUZZ_RADIUS: f32 = 2.; // km, how far the stored location of a user fuzzing it can be from the real one
+pub const LOCATION_UPDATE_INTERVAL: i64 = 60 * 10; // seconds, a location change sooner than this after the last one is ignored
+pub const SAME_LOCATION_EPSILON: f32 = 1e-5; // radians, about 60 m, closer locations are considered unchanged
+pub const MINIMUM_AGE: u32 = 18;
+pub const MAXIMUM_AGE: u32 = 127;
+
+pub const PROFILE_INTERESTS: &[&str] = &[
+    "art",
+    "board_games",
+    "cinema",
+    "cooking",
+    "dancing",
+    "fashion",
+    "fitness",
+    "gaming",
+    "hiking",
+    "music",
+    "nature",
+    "photography",
+    "reading",
+    "running",
+    "science",
+    "sports",
+    "technology",
+    "travel",
+    "volunteering",
+    "yoga",
+];
+pub const PROFILE_LANGUAGES: &[&str] = &[
+    "ar", "bn", "de", "el", "en", "es", "fa", "fr", "he", "hi", "id", "it", "ja", "ko", "nl", "pl",
+    "pt", "ro", "ru", "sv", "th", "tr", "uk", "vi", "zh",
+]; // ISO 639-1
+pub const PROFILE_PROMPTS: &[&str] = &[
+    "perfect_sunday",
+    "green_flag",
+    "unpopular_opinion",
+    "two_truths_and_a_lie",
+    "first_date_idea",
+    "currently_learning",
+];
+pub const PROFILE_INTERESTS_MAX: usize = 10;
+pub const PROFILE_LANGUAGES_MAX: usize = 5;
+pub const PROFILE_PROMPTS_MAX: usize = 3;
+pub const PROFILE_TEXT_MAX_LENGTH: usize = 100; // job and education
+pub const PROFILE_ANSWER_MAX_LENGTH: usize = 300;
+pub const HEIGHT_MIN: u16 = 100; // cm
+pub const HEIGHT_MAX: u16 = 250;
+pub const LAST_ACTIVE_MAX_DAYS: u16 = 365;
+pub const LOCAL_STORAGE_ROUTE: &str = "/storage"; // where the local object store is served
+pub const PHOTOS_MAX: usize = 6; // per user, see the display_order CHECK of Photos
+pub const PHOTO_MAX_DIMENSION: u32 = 8000; // pixels, width and height
+pub const PHOTO_MAX_DECODED_SIZE: u64 = 128 * 1024 * 1024; // bytes of the decoded image
+pub const PHOTO_PROCESSING_PERMITS: usize = 4; // photos decoded and rendered at once, each may hold PHOTO_MAX_DECODED_SIZE
+pub const PHOTO_THUMBNAIL_SIZE: u32 = 200; // pixels, largest side of the photo variants
+pub const PHOTO_CARD_SIZE: u32 = 800;
+pub const PHOTO_FULL_SIZE: u32 = 1600;
+pub const PHOTO_JPEG_QUALITY: u8 = 85;
+pub const PHOTO_BLURHASH_SIZE: u32 = 32; // pixels, largest side of the image the blurhash is computed from
+pub const PHOTO_BLURHASH_COMPONENTS: (u32, u32) = (4, 3); // horizontal and vertical, more is more detailed and longer
+pub const PHOTO_DELETION_SWEEP_INTERVAL: u64 = 10; // seconds
+pub const PHOTO_DELETION_BATCH_SIZE: usize = 100; // queued deletions handled per sweep
+pub const PHOTO_DELETION_RETRY_DELAY: i64 = 60; // seconds, doubled after each failed attempt
+pub const PHOTO_DELETION_MAX_RETRY_DELAY: i64 = 60 * 60 * 6; // seconds
+pub const PHOTO_RECONCILIATION_GRACE: i64 = 60 * 60; // seconds, younger objects may belong to an upload in progress
+pub const PHOTO_MIN_DIMENSION: u32 = 320; // pixels, smallest side accepted by the rules moderator
+pub const PHOTO_MAX_ASPECT_RATIO: f32 = 3.; // longest side over smallest side accepted by the rules moderator
+pub const PHOTO_DUPLICATE_DISTANCE: u32 = 8; // bits, photos whose perceptual hashes differ by this or less are near duplicates
+pub const PHOTO_HASH_MIN_BITS: u32 = 16; // perceptual hashes with fewer set or unset bits come from flat photos, they are not compared
+pub const API_VERSION_HEADER: &str = "api-version";
+pub const API_VERSION_LATEST: u8 = 2;
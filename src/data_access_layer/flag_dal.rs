@@ -0,0 +1,54 @@
+use crate::configs::app_state::AppState;
+use crate::my_errors::sqlite_errors::map_sqlite_error;
+use crate::my_errors::sqlite_errors::SqliteError;
+use rusqlite::{params, types::ToSqlOutput, ToSql};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Why an account was flagged for review
+#[derive(Clone, Copy, Debug)]
+pub enum FlagReason {
+    SwipeCadence,
+    LikeRatio,
+    OffDeckSwipe,
+    DuplicatePhoto, // uploaded a photo of another user
+}
+
+impl ToSql for FlagReason {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            FlagReason::SwipeCadence => Ok("swipe_cadence".into()),
+            FlagReason::LikeRatio => Ok("like_ratio".into()),
+            FlagReason::OffDeckSwipe => Ok("off_deck_swipe".into()),
+            FlagReason::DuplicatePhoto => Ok("duplicate_photo".into()),
+        }
+    }
+}
+
+// A user is flagged once per reason, flagging again only counts the occurrence
+pub fn flag_user(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    reason: FlagReason,
+) -> Result<(), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    binding
+        .prepare_cached(
+            "
+            INSERT INTO AccountFlags (flag_uuid, user_uuid, reason, last_flag_datetime) VALUES (?, ?, ?, ?)
+            ON CONFLICT (user_uuid, reason) DO UPDATE SET
+                occurrences = occurrences + 1,
+                last_flag_datetime = excluded.last_flag_datetime
+            ",
+        )
+        .map_err(map_sqlite_error)?
+        .execute(params![
+            Uuid::now_v7().to_string(),
+            user_uuid,
+            reason,
+            format!("{:?}", chrono::offset::Utc::now())
+        ])
+        .map_err(map_sqlite_error)?;
+
+    Ok(())
+}
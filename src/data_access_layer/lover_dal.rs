@@ -1,10 +1,15 @@
-use rusqlite::params;
+use rusqlite::{named_params, params};
 use serde::{Deserialize, Serialize};
 
 use crate::configs::app_state::AppState;
+use crate::data_access_layer::photo_dal::{
+    LegacyFirstPhoto, LegacyPhotoFields, PhotoDto, FIRST_PHOTO, PHOTOS,
+};
+use crate::data_access_layer::user_dal::{
+    from_json, DiscoveryCriteria, ProfileDetails, AGE, DISCOVERY_CONDITIONS, DISTANCE,
+};
 use crate::my_errors::sqlite_errors::map_sqlite_error;
 use crate::my_errors::sqlite_errors::SqliteError;
-use crate::requests::requests::Gender;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -21,7 +26,23 @@ pub struct LoveWithLover {
     pub age: u8,
     pub gender: String,
     pub description: String,
-    pub first_photo_url: Option<String>,
+    pub first_photo: Option<PhotoDto>,
+    #[serde(flatten)]
+    pub legacy_first_photo: Option<LegacyFirstPhoto>, // api version 1 only, set by the service layer
+    pub profile: ProfileDetails,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReceivedLike {
+    pub uuid: String,
+    pub name: String,
+    pub age: u8,
+    pub gender: String,
+    pub photos: Vec<PhotoDto>,
+    #[serde(flatten)]
+    pub legacy_photos: Option<LegacyPhotoFields>, // api version 1 only, set by the service layer
+    pub super_like: bool,
+    pub blurred: bool, // when blurred, only the age and gender are sent
 }
 
 pub fn create_lovers(
@@ -40,6 +61,39 @@ pub fn create_lovers(
     Ok(())
 }
 
+// Get the love relation between user_uuid and lover_uuid, and whether lover_uuid
+// already interacted with it (saw the match or posted a message)
+pub fn get_love_interaction(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    lover_uuid: String,
+) -> Result<(String, bool), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "
+            SELECT love_uuid,
+            CASE WHEN lover1 = :lover_uuid THEN seen_by_lover1 ELSE seen_by_lover2 END
+            OR EXISTS (
+                SELECT 1 FROM Messages
+                WHERE Messages.love_uuid = Lovers.love_uuid AND poster_uuid = :lover_uuid
+            ) as interacted
+            FROM Lovers
+            WHERE (lover1 = :user_uuid AND lover2 = :lover_uuid)
+            OR (lover1 = :lover_uuid AND lover2 = :user_uuid)
+            LIMIT 1
+            ",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement
+        .query_row(
+            named_params! {":user_uuid": user_uuid, ":lover_uuid": lover_uuid},
+            |row| Ok((row.get("love_uuid")?, row.get("interacted")?)),
+        )
+        .map_err(map_sqlite_error)
+}
+
 // Return true if user_uuid is in the loved_id relation
 pub fn user_in_love_relation(
     db: &Arc<AppState>,
@@ -67,15 +121,13 @@ pub fn get_lovers(
 ) -> Result<Vec<LoveWithLover>, SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
-        .prepare_cached(
+        .prepare_cached(&format!(
             "
-            SELECT *, url FROM Users
+            SELECT *, {FIRST_PHOTO} as first_photo, {AGE} as age FROM Users
             JOIN Lovers ON Users.user_uuid = Lovers.lover1
-            LEFT JOIN Photos ON Lovers.lover1 = Photos.user_uuid 
             WHERE Lovers.lover2 = ?
-            LIMIT 1 -- select only one photo
-            ",
-        )
+            "
+        ))
         .map_err(map_sqlite_error)?;
     let result_rows1 = statement
         .query_map(params![user_uuid], |row| {
@@ -91,22 +143,22 @@ pub fn get_lovers(
                 age: row.get("age")?,
                 gender: row.get("gender")?,
                 description: row.get("description")?,
-                first_photo_url: row.get("url")?,
+                first_photo: from_json(row, "first_photo")?,
+                legacy_first_photo: None,
+                profile: ProfileDetails::from_row(row)?,
             })
         })
         .map_err(map_sqlite_error)?;
 
     let binding = db.connection.get().unwrap();
     let mut statement = binding
-        .prepare_cached(
+        .prepare_cached(&format!(
             "
-            SELECT *, url FROM Users
+            SELECT *, {FIRST_PHOTO} as first_photo, {AGE} as age FROM Users
             JOIN Lovers ON Users.user_uuid = Lovers.lover2
-            LEFT JOIN Photos ON Lovers.lover2 = Photos.user_uuid 
             WHERE Lovers.lover1 = ?
-            LIMIT 1 -- select only one photo
-            ",
-        )
+            "
+        ))
         .map_err(map_sqlite_error)?;
     let result_rows2 = statement
         .query_map(params![user_uuid], |row| {
@@ -122,7 +174,9 @@ pub fn get_lovers(
                 age: row.get("age")?,
                 gender: row.get("gender")?,
                 description: row.get("description")?,
-                first_photo_url: row.get("url")?,
+                first_photo: from_json(row, "first_photo")?,
+                legacy_first_photo: None,
+                profile: ProfileDetails::from_row(row)?,
             })
         })
         .map_err(map_sqlite_error)?;
@@ -164,54 +218,194 @@ pub fn tick_love(
 
 pub fn potential_matches_count(
     db: &Arc<AppState>,
-    user_uuid: String,
-    looking_for: Gender,
-    search_radius: u16,
-    latitude: f32,
-    longitude: f32,
-    age_min: u8,
-    age_max: u8,
+    criteria: &DiscoveryCriteria,
 ) -> Result<usize, SqliteError> {
     let binding = db.connection.get().unwrap();
     // todo : potential sql optimization, selecting from MatchingResults ?
     let mut statement = binding
-        .prepare_cached(
+        .prepare_cached(&format!(
             "
                 SELECT count(*) as count, 
-                6371 * acos(
-                    sin(?) * sin(latitude) +
-                    cos(?) * cos(latitude) * cos(? - longitude)
-                ) as distance
+                {DISTANCE} as distance,
+                {AGE} as age
                 FROM Users
-                WHERE user_uuid <> ?
-                AND gender = ?
-                AND age <= ?
-                AND age >= ?
-                AND user_uuid NOT IN ( -- don't pick someone that the user has already swipped
-                    SELECT swiped as user_uuid
-                    FROM MatchingResults
-                    WHERE swiper = ?
+                WHERE {DISCOVERY_CONDITIONS}
+               "
+        ))
+        .map_err(map_sqlite_error)?;
+    let bounding_box = criteria.bounding_box();
+    let potential_matches_count = statement
+        .query_row(criteria.named_params(db, &bounding_box).as_slice(), |row| {
+            row.get("count")
+        })
+        .map_err(map_sqlite_error)?;
+
+    Ok(potential_matches_count)
+}
+
+// Users who love user_uuid and that user_uuid has not swiped yet, most recent likes first
+pub fn get_likes_received(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<ReceivedLike>, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(&format!(
+            "
+            SELECT Users.*,
+            {AGE} as age,
+            {PHOTOS} as photos,
+            MatchingResults.love = 2 as super_like
+            FROM MatchingResults
+            JOIN Users ON Users.user_uuid = MatchingResults.swiper
+            WHERE MatchingResults.swiped = :user_uuid AND MatchingResults.love >= 1
+            AND MatchingResults.undone = 0
+            AND MatchingResults.swiper NOT IN ( -- likes already answered are either a match or a pass
+                SELECT swiped FROM MatchingResults WHERE swiper = :user_uuid AND undone = 0
+            )
+            GROUP BY Users.user_uuid
+            ORDER BY MatchingResults.match_id DESC
+            LIMIT :limit OFFSET :offset
+            "
+        ))
+        .map_err(map_sqlite_error)?;
+    let result_rows = statement
+        .query_map(
+            named_params! {":user_uuid": user_uuid, ":limit": limit, ":offset": offset},
+            |row| {
+                Ok(ReceivedLike {
+                    uuid: row.get("user_uuid")?,
+                    name: row.get("name")?,
+                    age: row.get("age")?,
+                    gender: row.get("gender")?,
+                    photos: from_json(row, "photos")?,
+                    legacy_photos: None,
+                    super_like: row.get("super_like")?,
+                    blurred: false,
+                })
+            },
+        )
+        .map_err(map_sqlite_error)?;
+
+    let mut likes = Vec::new();
+    for like in result_rows {
+        likes.push(like.map_err(map_sqlite_error)?);
+    }
+
+    Ok(likes)
+}
+
+pub fn likes_received_count(db: &Arc<AppState>, user_uuid: String) -> Result<usize, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "
+            SELECT COUNT(*) as count
+            FROM MatchingResults
+            WHERE swiped = :user_uuid AND love >= 1 AND undone = 0
+            AND swiper NOT IN (
+                SELECT swiped FROM MatchingResults WHERE swiper = :user_uuid AND undone = 0
+            )
+            ",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement
+        .query_row(named_params! {":user_uuid": user_uuid}, |row| {
+            row.get("count")
+        })
+        .map_err(map_sqlite_error)
+}
+
+// Position of the like of `liker` in get_likes_received, None when there is no such like or the user
+// already answered it
+pub fn like_received_position(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    liker: String,
+) -> Result<Option<usize>, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "
+            SELECT EXISTS (
+                SELECT 1 FROM MatchingResults
+                WHERE swiper = :liker AND swiped = :user_uuid AND love >= 1 AND undone = 0
+            ) as liked,
+            :liker IN (
+                SELECT swiped FROM MatchingResults WHERE swiper = :user_uuid AND undone = 0
+            ) as answered,
+            (
+                SELECT COUNT(*) FROM MatchingResults
+                WHERE swiped = :user_uuid AND love >= 1 AND undone = 0
+                AND swiper NOT IN (
+                    SELECT swiped FROM MatchingResults WHERE swiper = :user_uuid AND undone = 0
+                )
+                AND match_id > (
+                    SELECT match_id FROM MatchingResults WHERE swiper = :liker AND swiped = :user_uuid
                 )
-                AND distance < ?
-               ",
+            ) as position
+            ",
         )
         .map_err(map_sqlite_error)?;
-    let potential_matches_count = statement
+
+    let (liked, answered, position): (bool, bool, usize) = statement
         .query_row(
-            params![
-                latitude,
-                latitude,
-                longitude,
-                user_uuid,
-                looking_for,
-                age_max,
-                age_min,
-                user_uuid,
-                search_radius
-            ],
-            |row| row.get("count"),
+            named_params! {":user_uuid": user_uuid, ":liker": liker},
+            |row| {
+                Ok((
+                    row.get("liked")?,
+                    row.get("answered")?,
+                    row.get("position")?,
+                ))
+            },
         )
         .map_err(map_sqlite_error)?;
+    Ok((liked && !answered).then_some(position))
+}
 
-    Ok(potential_matches_count)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access_layer::user_dal::swipe_user;
+    use crate::requests::requests::SwipeKind;
+    use crate::utilities::test_fixtures::{app_state, insert_user, UserFixture};
+
+    #[tokio::test]
+    async fn like_received_position_follows_get_likes_received() {
+        let state = app_state().await;
+        let ann = insert_user(&state, UserFixture::default());
+        let man = |name| UserFixture {
+            name,
+            gender: "male",
+            looking_for: "female",
+            ..Default::default()
+        };
+        let (bob, carl) = (
+            insert_user(&state, man("Bob")),
+            insert_user(&state, man("Carl")),
+        );
+        swipe_user(&state, bob.clone(), ann.clone(), SwipeKind::Like).unwrap();
+        swipe_user(&state, carl.clone(), ann.clone(), SwipeKind::SuperLike).unwrap();
+
+        let likes = get_likes_received(&state, ann.clone(), 10, 0).unwrap();
+        assert_eq!(likes.len(), 2);
+        for (index, like) in likes.iter().enumerate() {
+            let position = like_received_position(&state, ann.clone(), like.uuid.clone());
+            assert_eq!(position.unwrap(), Some(index));
+        }
+        assert_eq!(
+            like_received_position(&state, bob.clone(), ann.clone()).unwrap(),
+            None
+        );
+        // Once Ann answered Carl, his like leaves the list
+        swipe_user(&state, ann.clone(), carl.clone(), SwipeKind::Pass).unwrap();
+        assert_eq!(
+            like_received_position(&state, ann.clone(), carl).unwrap(),
+            None
+        );
+        assert_eq!(like_received_position(&state, ann, bob).unwrap(), Some(0));
+    }
 }
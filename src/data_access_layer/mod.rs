@@ -1,6 +1,8 @@
 pub mod feedback_dal;
+pub mod flag_dal;
 pub mod lover_dal;
 pub mod message_dal;
 pub mod photo_dal;
+pub mod preference_dal;
 pub mod trace_dal;
 pub mod user_dal;
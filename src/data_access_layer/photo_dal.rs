@@ -1,7 +1,10 @@
 use crate::configs::app_state::AppState;
+use crate::constants::constants::PHOTOS_MAX;
 use crate::my_errors::sqlite_errors::map_sqlite_error;
 use crate::my_errors::sqlite_errors::SqliteError;
-use rusqlite::params;
+use crate::requests::requests::PhotoStatus;
+use crate::utilities::api_version::ApiVersion;
+use rusqlite::{named_params, params, params_from_iter, Transaction};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -10,54 +13,251 @@ pub struct Photo {
     pub photo_id: usize,
     pub photo_uuid: String,
     pub user_uuid: String,
-    pub url: String,
+    pub variants: PhotoVariants,
+    pub blurhash: Option<String>,
     pub display_order: usize,
+    pub status: PhotoStatus,
+    pub perceptual_hash: Option<u64>,
+    pub duplicate_of: Option<String>, // photo_uuid of a near identical photo of another user
 }
 
-pub fn create_user_photo(
-    db: &Arc<AppState>,
-    photo_uuid: String,
-    user_uuid: String,
-    url: String,
-    display_order: usize,
-) -> Result<(), SqliteError> {
+// Url of each variant of a photo, see utilities::images::PhotoVariant
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PhotoVariants {
+    pub thumbnail: String,
+    pub card: String,
+    pub full: String,
+}
+
+// Photos of a user as sent to clients, ordered by display_order
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PhotoDto {
+    pub uuid: String,
+    pub url: String, // full variant
+    pub display_order: usize,
+    pub variants: PhotoVariants,
+    pub blurhash: Option<String>,
+    pub status: PhotoStatus,
+}
+
+// JSON array of the approved PhotoDto of the Users row, to be read with user_dal::from_json
+pub const PHOTOS: &str = "
+    (
+        SELECT json_group_array(json_object(
+            'uuid', photo_uuid,
+            'url', url,
+            'display_order', display_order,
+            'variants', json_object('thumbnail', thumbnail_url, 'card', card_url, 'full', url),
+            'blurhash', blurhash,
+            'status', status
+        ))
+        FROM (
+            SELECT * FROM Photos WHERE Photos.user_uuid = Users.user_uuid AND status = 'approved'
+            ORDER BY display_order
+        )
+    )
+";
+
+// Same as PHOTOS with the pending and rejected photos too, only for the owner of the photos
+pub const OWN_PHOTOS: &str = "
+    (
+        SELECT json_group_array(json_object(
+            'uuid', photo_uuid,
+            'url', url,
+            'display_order', display_order,
+            'variants', json_object('thumbnail', thumbnail_url, 'card', card_url, 'full', url),
+            'blurhash', blurhash,
+            'status', status
+        ))
+        FROM (SELECT * FROM Photos WHERE Photos.user_uuid = Users.user_uuid ORDER BY display_order)
+    )
+";
+
+// JSON PhotoDto of the first approved photo of the Users row, 'null' if the user has none
+pub const FIRST_PHOTO: &str = "
+    COALESCE((
+        SELECT json_object(
+            'uuid', photo_uuid,
+            'url', url,
+            'display_order', display_order,
+            'variants', json_object('thumbnail', thumbnail_url, 'card', card_url, 'full', url),
+            'blurhash', blurhash,
+            'status', status
+        )
+        FROM Photos WHERE Photos.user_uuid = Users.user_uuid AND status = 'approved'
+        ORDER BY display_order
+        LIMIT 1
+    ), 'null')
+";
+
+// Photos as comma separated strings, replaced by the PhotoDto list in api version 2.
+// Still sent to the clients of version 1, the strings of a same user are in the same order
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LegacyPhotoFields {
+    pub photo_urls: Option<String>,
+    pub photo_thumbnail_urls: Option<String>,
+    pub photo_card_urls: Option<String>,
+    pub photo_blurhashes: Option<String>, // space separated, blurhashes can contain commas
+    pub photo_display_orders: Option<String>,
+}
+
+impl LegacyPhotoFields {
+    pub fn for_version(api_version: ApiVersion, photos: &[PhotoDto]) -> Option<LegacyPhotoFields> {
+        if !api_version.legacy_photo_fields() {
+            return None;
+        }
+        // Fields are null when the user has no photo, as GROUP_CONCAT used to return
+        let join = |field: fn(&PhotoDto) -> String, separator: &str| {
+            (!photos.is_empty()).then(|| {
+                photos
+                    .iter()
+                    .map(field)
+                    .collect::<Vec<String>>()
+                    .join(separator)
+            })
+        };
+        Some(LegacyPhotoFields {
+            photo_urls: join(|photo| photo.url.clone(), ","),
+            photo_thumbnail_urls: join(|photo| photo.variants.thumbnail.clone(), ","),
+            photo_card_urls: join(|photo| photo.variants.card.clone(), ","),
+            photo_blurhashes: join(|photo| photo.blurhash.clone().unwrap_or_default(), " "),
+            photo_display_orders: join(|photo| photo.display_order.to_string(), ","),
+        })
+    }
+}
+
+// First photo fields of a lover, replaced by first_photo in api version 2
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LegacyFirstPhoto {
+    pub first_photo_url: Option<String>,
+    pub first_photo_thumbnail_url: Option<String>,
+    pub first_photo_blurhash: Option<String>,
+}
+
+impl LegacyFirstPhoto {
+    pub fn for_version(
+        api_version: ApiVersion,
+        first_photo: Option<&PhotoDto>,
+    ) -> Option<LegacyFirstPhoto> {
+        if !api_version.legacy_photo_fields() {
+            return None;
+        }
+        Some(LegacyFirstPhoto {
+            first_photo_url: first_photo.map(|photo| photo.url.clone()),
+            first_photo_thumbnail_url: first_photo.map(|photo| photo.variants.thumbnail.clone()),
+            first_photo_blurhash: first_photo.and_then(|photo| photo.blurhash.clone()),
+        })
+    }
+}
+
+impl Photo {
+    // Reads a Photos row
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Photo> {
+        Ok(Photo {
+            photo_id: row.get("photo_id")?,
+            photo_uuid: row.get("photo_uuid")?,
+            user_uuid: row.get("user_uuid")?,
+            variants: PhotoVariants {
+                thumbnail: row.get("thumbnail_url")?,
+                card: row.get("card_url")?,
+                full: row.get("url")?,
+            },
+            blurhash: row.get("blurhash")?,
+            display_order: row.get("display_order")?,
+            status: row.get("status")?,
+            // Stored as a signed integer, the bits are the same
+            perceptual_hash: row
+                .get::<_, Option<i64>>("perceptual_hash")?
+                .map(|hash| hash as u64),
+            duplicate_of: row.get("duplicate_of")?,
+        })
+    }
+}
+
+// A photo about to be saved, its objects are already stored
+pub struct NewPhoto {
+    pub photo_uuid: String,
+    pub user_uuid: String,
+    pub variants: PhotoVariants,
+    pub blurhash: String,
+    pub perceptual_hash: u64,
+    pub duplicate_of: Option<String>,
+    pub status: PhotoStatus,
+}
+
+// The photo goes after the last photo of the user. Its display order is computed by the INSERT
+// itself, so that concurrent uploads can't get the same one.
+// Conflict when the user already has PHOTOS_MAX photos
+pub fn create_user_photo(db: &Arc<AppState>, photo: NewPhoto) -> Result<(), SqliteError> {
     let binding = db.connection.get().unwrap();
-    binding
+    let inserted = binding
         .prepare_cached(
-            "INSERT INTO Photos (photo_uuid, user_uuid, url, display_order) VALUES (?, ?, ?, ?)",
+            "
+            INSERT INTO Photos (photo_uuid, user_uuid, url, thumbnail_url, card_url, blurhash, perceptual_hash, duplicate_of, display_order, status)
+            SELECT :photo_uuid, :user_uuid, :url, :thumbnail_url, :card_url, :blurhash, :perceptual_hash, :duplicate_of,
+                COALESCE(MAX(display_order), 0) + 1, :status
+            FROM Photos WHERE user_uuid = :user_uuid
+            HAVING COUNT(*) < :photos_max
+            ",
         )
         .map_err(map_sqlite_error)?
-        .execute(params![photo_uuid, user_uuid, url, display_order])
+        .execute(named_params! {
+            ":photo_uuid": photo.photo_uuid,
+            ":user_uuid": photo.user_uuid,
+            ":url": photo.variants.full,
+            ":thumbnail_url": photo.variants.thumbnail,
+            ":card_url": photo.variants.card,
+            ":blurhash": photo.blurhash,
+            ":perceptual_hash": photo.perceptual_hash as i64,
+            ":duplicate_of": photo.duplicate_of,
+            ":status": photo.status,
+            ":photos_max": PHOTOS_MAX,
+        })
         .map_err(map_sqlite_error)?;
+    if inserted == 0 {
+        return Err(SqliteError::Conflict);
+    }
 
     Ok(())
 }
 
-pub fn delete_photo(db: &Arc<AppState>, photo_uuid: String) -> Result<(), SqliteError> {
-    let binding = db.connection.get().unwrap();
-    binding
-        .prepare_cached("DELETE FROM Photos WHERE photo_uuid = ?")
+// Delete the photo and move the next photos of the user up, in one transaction.
+// Its objects are queued for deletion, see photos_service::delete_queued_photos
+pub fn delete_photo(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    photo_uuid: String,
+) -> Result<(), SqliteError> {
+    let mut binding = db.connection.get().unwrap();
+    let tx = binding.transaction().map_err(map_sqlite_error)?;
+    tx.prepare_cached("DELETE FROM Photos WHERE photo_uuid = ?")
         .map_err(map_sqlite_error)?
         .execute(params![photo_uuid])
         .map_err(map_sqlite_error)?;
+    tx.prepare_cached("INSERT OR IGNORE INTO PhotoDeletions (photo_uuid) VALUES (?)")
+        .map_err(map_sqlite_error)?
+        .execute(params![photo_uuid])
+        .map_err(map_sqlite_error)?;
+    let remaining_uuids = tx
+        .prepare_cached("SELECT photo_uuid FROM Photos WHERE user_uuid = ? ORDER BY display_order")
+        .map_err(map_sqlite_error)?
+        .query_map(params![user_uuid], |row| row.get("photo_uuid"))
+        .map_err(map_sqlite_error)?
+        .collect::<Result<Vec<String>, rusqlite::Error>>()
+        .map_err(map_sqlite_error)?;
+    write_display_orders(&tx, &user_uuid, &remaining_uuids)?;
+    tx.commit().map_err(map_sqlite_error)?;
+
     Ok(())
 }
 
 pub fn get_user_photos(db: &Arc<AppState>, user_uuid: String) -> Result<Vec<Photo>, SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
-        .prepare_cached("SELECT * FROM Photos WHERE user_uuid = ?")
+        .prepare_cached("SELECT * FROM Photos WHERE user_uuid = ? ORDER BY display_order")
         .map_err(map_sqlite_error)?;
     let result_rows = statement
-        .query_map(params![user_uuid], |row| {
-            Ok(Photo {
-                photo_id: row.get("photo_id")?,
-                photo_uuid: row.get("photo_uuid")?,
-                user_uuid: row.get("user_uuid")?,
-                url: row.get("url")?,
-                display_order: row.get("display_order")?,
-            })
-        })
+        .query_map(params![user_uuid], Photo::from_row)
         .map_err(map_sqlite_error)?;
 
     let mut photos = Vec::new();
@@ -68,62 +268,320 @@ pub fn get_user_photos(db: &Arc<AppState>, user_uuid: String) -> Result<Vec<Phot
     Ok(photos)
 }
 
-pub fn shift_order_photos(
-    db: &Arc<AppState>,
-    user_uuid: String,
-    order_shift: usize,
-) -> Result<(), SqliteError> {
+// Photos uploaded before variants existed, their only object is the original upload
+// with its metadata, used for every variant. And photos uploaded before blurhashes existed
+pub fn get_unprocessed_photos(db: &Arc<AppState>) -> Result<Vec<Photo>, SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
         .prepare_cached(
-            "
-        UPDATE Photos
-        SET display_order = display_order -1
-        WHERE user_uuid = ?
-        AND display_order > ?
-        ",
+            "SELECT * FROM Photos
+                WHERE thumbnail_url = url OR blurhash IS NULL OR perceptual_hash IS NULL",
         )
         .map_err(map_sqlite_error)?;
+    let result_rows = statement
+        .query_map([], Photo::from_row)
+        .map_err(map_sqlite_error)?;
 
-    statement
-        .execute(params![user_uuid, order_shift])
+    let mut photos = Vec::new();
+    for photo in result_rows {
+        photos.push(photo.map_err(map_sqlite_error)?)
+    }
+
+    Ok(photos)
+}
+
+pub fn update_processed_photo(
+    db: &Arc<AppState>,
+    photo_uuid: String,
+    variants: &PhotoVariants,
+    blurhash: String,
+    perceptual_hash: u64,
+) -> Result<(), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    binding
+        .prepare_cached(
+            "
+            UPDATE Photos SET url = ?, thumbnail_url = ?, card_url = ?, blurhash = ?, perceptual_hash = ?
+            WHERE photo_uuid = ?
+            ",
+        )
+        .map_err(map_sqlite_error)?
+        .execute(params![
+            variants.full,
+            variants.thumbnail,
+            variants.card,
+            blurhash,
+            perceptual_hash as i64,
+            photo_uuid
+        ])
         .map_err(map_sqlite_error)?;
 
     Ok(())
 }
 
-pub fn switch_order_photos(
+// Give the photos of the user the display orders 1, 2, 3... following photo_uuids, which should
+// hold every photo of the user
+pub fn reorder_photos(
     db: &Arc<AppState>,
-    order1: usize,
-    order2: usize,
-    photo_uuid1: String,
-    photo_uuid2: String,
+    user_uuid: String,
+    photo_uuids: &[String],
 ) -> Result<(), SqliteError> {
     let mut binding = db.connection.get().unwrap();
     let tx = binding.transaction().map_err(map_sqlite_error)?;
-    tx.prepare_cached(
-        "
-        UPDATE Photos
-        SET display_order = ?
-        WHERE photo_uuid = ?
-        ",
-    )
-    .map_err(map_sqlite_error)?
-    .execute(params![order1, photo_uuid2])
-    .map_err(map_sqlite_error)?;
+    write_display_orders(&tx, &user_uuid, photo_uuids)?;
+    tx.commit().map_err(map_sqlite_error)?;
 
-    tx.prepare_cached(
-        "
-        UPDATE Photos
-        SET display_order = ?
-        WHERE photo_uuid = ?
-        ",
+    Ok(())
+}
+
+// display_order is unique per user and its CHECK leaves no room for temporary values, so updating
+// the orders one by one could collide. The photos of the user are copied, deleted and inserted back
+// with their new order instead, every other column is kept
+fn write_display_orders(
+    tx: &Transaction,
+    user_uuid: &str,
+    photo_uuids: &[String],
+) -> Result<(), SqliteError> {
+    tx.execute(
+        "CREATE TEMP TABLE PhotosReorder AS SELECT * FROM Photos WHERE user_uuid = ?",
+        params![user_uuid],
     )
-    .map_err(map_sqlite_error)?
-    .execute(params![order2, photo_uuid1])
     .map_err(map_sqlite_error)?;
+    for (index, photo_uuid) in photo_uuids.iter().enumerate() {
+        tx.execute(
+            "UPDATE temp.PhotosReorder SET display_order = ? WHERE photo_uuid = ?",
+            params![index + 1, photo_uuid],
+        )
+        .map_err(map_sqlite_error)?;
+    }
+    tx.execute("DELETE FROM Photos WHERE user_uuid = ?", params![user_uuid])
+        .map_err(map_sqlite_error)?;
+    tx.execute("INSERT INTO Photos SELECT * FROM temp.PhotosReorder", [])
+        .map_err(map_sqlite_error)?;
+    tx.execute("DROP TABLE temp.PhotosReorder", [])
+        .map_err(map_sqlite_error)?;
+
+    Ok(())
+}
+
+pub struct PhotoDeletion {
+    pub photo_uuid: String,
+    pub attempts: u32,
+}
 
+pub fn get_all_photos(db: &Arc<AppState>) -> Result<Vec<Photo>, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached("SELECT * FROM Photos")
+        .map_err(map_sqlite_error)?;
+    let result_rows = statement
+        .query_map([], Photo::from_row)
+        .map_err(map_sqlite_error)?;
+
+    let mut photos = Vec::new();
+    for photo in result_rows {
+        photos.push(photo.map_err(map_sqlite_error)?)
+    }
+
+    Ok(photos)
+}
+
+// Queue photos whose objects should be deleted, photos already queued are left as is
+pub fn queue_photo_deletions(
+    db: &Arc<AppState>,
+    photo_uuids: &[String],
+) -> Result<(), SqliteError> {
+    let mut binding = db.connection.get().unwrap();
+    let tx = binding.transaction().map_err(map_sqlite_error)?;
+    for photo_uuid in photo_uuids {
+        tx.prepare_cached("INSERT OR IGNORE INTO PhotoDeletions (photo_uuid) VALUES (?)")
+            .map_err(map_sqlite_error)?
+            .execute(params![photo_uuid])
+            .map_err(map_sqlite_error)?;
+    }
     tx.commit().map_err(map_sqlite_error)?;
 
     Ok(())
 }
+
+// Every queued deletion, due or waiting for a retry
+pub fn get_queued_photo_deletions(db: &Arc<AppState>) -> Result<Vec<String>, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached("SELECT photo_uuid FROM PhotoDeletions")
+        .map_err(map_sqlite_error)?;
+    let result_rows = statement
+        .query_map([], |row| row.get("photo_uuid"))
+        .map_err(map_sqlite_error)?;
+
+    let mut photo_uuids = Vec::new();
+    for photo_uuid in result_rows {
+        photo_uuids.push(photo_uuid.map_err(map_sqlite_error)?)
+    }
+
+    Ok(photo_uuids)
+}
+
+// Up to `limit` queued deletions whose next attempt is due, the oldest first
+pub fn get_due_photo_deletions(
+    db: &Arc<AppState>,
+    limit: usize,
+) -> Result<Vec<PhotoDeletion>, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "SELECT photo_uuid, attempts FROM PhotoDeletions
+                WHERE julianday(next_attempt_datetime) <= julianday('now')
+                ORDER BY deletion_id
+                LIMIT ?",
+        )
+        .map_err(map_sqlite_error)?;
+    let result_rows = statement
+        .query_map(params![limit], |row| {
+            Ok(PhotoDeletion {
+                photo_uuid: row.get("photo_uuid")?,
+                attempts: row.get("attempts")?,
+            })
+        })
+        .map_err(map_sqlite_error)?;
+
+    let mut deletions = Vec::new();
+    for deletion in result_rows {
+        deletions.push(deletion.map_err(map_sqlite_error)?)
+    }
+
+    Ok(deletions)
+}
+
+pub fn complete_photo_deletion(db: &Arc<AppState>, photo_uuid: String) -> Result<(), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    binding
+        .prepare_cached("DELETE FROM PhotoDeletions WHERE photo_uuid = ?")
+        .map_err(map_sqlite_error)?
+        .execute(params![photo_uuid])
+        .map_err(map_sqlite_error)?;
+
+    Ok(())
+}
+
+pub fn postpone_photo_deletion(
+    db: &Arc<AppState>,
+    photo_uuid: String,
+    next_attempt_datetime: String,
+    error: String,
+) -> Result<(), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    binding
+        .prepare_cached(
+            "UPDATE PhotoDeletions
+                SET attempts = attempts + 1,
+                next_attempt_datetime = ?,
+                last_error = ?
+                WHERE photo_uuid = ?",
+        )
+        .map_err(map_sqlite_error)?
+        .execute(params![next_attempt_datetime, error, photo_uuid])
+        .map_err(map_sqlite_error)?;
+
+    Ok(())
+}
+
+// Up to `limit` photos with the given status after the first `offset` ones, the oldest first
+pub fn get_photos_by_status(
+    db: &Arc<AppState>,
+    status: PhotoStatus,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<Photo>, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached("SELECT * FROM Photos WHERE status = ? ORDER BY photo_id LIMIT ? OFFSET ?")
+        .map_err(map_sqlite_error)?;
+    let result_rows = statement
+        .query_map(params![status, limit, offset], Photo::from_row)
+        .map_err(map_sqlite_error)?;
+
+    let mut photos = Vec::new();
+    for photo in result_rows {
+        photos.push(photo.map_err(map_sqlite_error)?)
+    }
+
+    Ok(photos)
+}
+
+pub fn set_photo_status(
+    db: &Arc<AppState>,
+    photo_uuid: String,
+    status: PhotoStatus,
+) -> Result<(), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let updated = binding
+        .prepare_cached("UPDATE Photos SET status = ? WHERE photo_uuid = ?")
+        .map_err(map_sqlite_error)?
+        .execute(params![status, photo_uuid])
+        .map_err(map_sqlite_error)?;
+    if updated == 0 {
+        return Err(SqliteError::NotFound);
+    }
+
+    Ok(())
+}
+
+pub struct PhotoHash {
+    pub photo_uuid: String,
+    pub user_uuid: String,
+    pub perceptual_hash: u64,
+}
+
+// Photos that may be near duplicates of the one with this perceptual hash, to be checked with
+// images::hash_distance. The 64 bits are cut in 9 bands, 8 of 7 bits and one of 8 : hashes
+// differing by PHOTO_DUPLICATE_DISTANCE bits or less have at least one identical band, each band
+// has its index, see migrations/018_photo_hash_bands.sql.
+// Photos uploaded before hashes existed have none until reprocessed
+pub fn get_duplicate_candidates(
+    db: &Arc<AppState>,
+    perceptual_hash: u64,
+) -> Result<Vec<PhotoHash>, SqliteError> {
+    let bands = hash_bands(perceptual_hash);
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "SELECT photo_uuid, user_uuid, perceptual_hash FROM Photos
+                WHERE (perceptual_hash >> 0) & 127 = ?
+                OR (perceptual_hash >> 7) & 127 = ?
+                OR (perceptual_hash >> 14) & 127 = ?
+                OR (perceptual_hash >> 21) & 127 = ?
+                OR (perceptual_hash >> 28) & 127 = ?
+                OR (perceptual_hash >> 35) & 127 = ?
+                OR (perceptual_hash >> 42) & 127 = ?
+                OR (perceptual_hash >> 49) & 127 = ?
+                OR (perceptual_hash >> 56) & 255 = ?",
+        )
+        .map_err(map_sqlite_error)?;
+    let result_rows = statement
+        .query_map(params_from_iter(bands), |row| {
+            Ok(PhotoHash {
+                photo_uuid: row.get("photo_uuid")?,
+                user_uuid: row.get("user_uuid")?,
+                perceptual_hash: row.get::<_, i64>("perceptual_hash")? as u64,
+            })
+        })
+        .map_err(map_sqlite_error)?;
+
+    let mut hashes = Vec::new();
+    for hash in result_rows {
+        hashes.push(hash.map_err(map_sqlite_error)?)
+    }
+
+    Ok(hashes)
+}
+
+// Same bands as the indexes of migrations/018_photo_hash_bands.sql
+fn hash_bands(perceptual_hash: u64) -> [i64; 9] {
+    let mut bands = [0; 9];
+    for (i, band) in bands.iter_mut().enumerate() {
+        let mask = if i == 8 { 255 } else { 127 };
+        *band = ((perceptual_hash >> (7 * i)) & mask) as i64;
+    }
+    bands
+}
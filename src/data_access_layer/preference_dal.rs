@@ -0,0 +1,49 @@
+use crate::configs::app_state::AppState;
+use crate::my_errors::sqlite_errors::map_sqlite_error;
+use crate::my_errors::sqlite_errors::SqliteError;
+use crate::requests::requests::DiscoveryPreferences;
+use rusqlite::params;
+use std::sync::Arc;
+
+// Users who never saved preferences have none
+pub fn get_discovery_preferences(
+    db: &Arc<AppState>,
+    user_uuid: String,
+) -> Result<DiscoveryPreferences, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached("SELECT preferences FROM DiscoveryPreferences WHERE user_uuid = ?")
+        .map_err(map_sqlite_error)?;
+
+    let preferences = statement.query_row(params![user_uuid], |row| row.get::<_, String>(0));
+    match preferences.map_err(map_sqlite_error) {
+        Ok(preferences) => {
+            serde_json::from_str(&preferences).map_err(|_| SqliteError::SqliteFailureNoText)
+        }
+        Err(SqliteError::NotFound) => Ok(DiscoveryPreferences::default()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn set_discovery_preferences(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    preferences: &DiscoveryPreferences,
+) -> Result<(), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    binding
+        .prepare_cached(
+            "
+            INSERT INTO DiscoveryPreferences (user_uuid, preferences) VALUES (?, ?)
+            ON CONFLICT (user_uuid) DO UPDATE SET preferences = excluded.preferences
+            ",
+        )
+        .map_err(map_sqlite_error)?
+        .execute(params![
+            user_uuid,
+            serde_json::to_string(preferences).unwrap()
+        ])
+        .map_err(map_sqlite_error)?;
+
+    Ok(())
+}
@@ -1,11 +1,17 @@
 use chrono;
-use rusqlite::params;
-use serde::{Deserialize, Serialize};
+use rusqlite::{named_params, params, ToSql};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::configs::app_state::AppState;
+use crate::constants::constants::LOCATION_UPDATE_INTERVAL;
+use crate::data_access_layer::photo_dal::{LegacyPhotoFields, PhotoDto, OWN_PHOTOS, PHOTOS};
 use crate::my_errors::sqlite_errors::map_sqlite_error;
 use crate::my_errors::sqlite_errors::SqliteError;
 use crate::requests::requests;
+use crate::requests::requests::{
+    DiscoveryPreferences, FilterStrength, Gender, ProfilePrompt, SwipeKind,
+};
+use crate::utilities::geo::{distance_label, BoundingBox};
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -17,7 +23,8 @@ pub struct User {
     pub password: String,
     pub email: String,
     pub last_seen: String,
-    pub age: u8,
+    pub birthdate: String,
+    pub age: u8, // computed from birthdate
     pub latitude: f32,
     pub longitude: f32,
     pub gender: String,
@@ -26,8 +33,18 @@ pub struct User {
     pub looking_for_age_min: u8,
     pub looking_for_age_max: u8,
     pub description: String,
-    pub photo_urls: Option<String>,
-    pub photo_display_orders: Option<String>,
+    pub recycle_passed_profiles: bool, // passed users are shown again after swipes.pass_cooldown_days
+    pub travel_latitude: Option<f32>,
+    pub travel_longitude: Option<f32>,
+    pub travel_label: Option<String>,
+    pub travel_expiry: Option<String>,
+    pub location_offset_north: Option<f32>, // km, set when the user fuzzes their location
+    pub location_offset_east: Option<f32>,
+    pub location_updated_at: String,
+    pub profile: ProfileDetails,
+    pub photos: Vec<PhotoDto>,
+    #[serde(flatten)]
+    pub legacy_photos: Option<LegacyPhotoFields>, // api version 1 only, set by the service layer
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -38,18 +55,291 @@ pub struct PotentialLover {
     pub age: u8,
     pub gender: String,
     pub description: String,
+    pub distance: String, // bucketed, see utilities::geo::distance_label
+    pub photos: Vec<PhotoDto>,
+    #[serde(flatten)]
+    pub legacy_photos: Option<LegacyPhotoFields>, // api version 1 only, set by the service layer
+    pub liked_you: bool, // the potential lover already loves the searching user
+    pub travelling_to: Option<String>, // label of the travel location, distance is computed from it
+    pub profile: ProfileDetails,
+    pub compatibility: Compatibility, // computed by the discovery ranking
+}
+
+// How well a potential lover fits the searching user, every score is between 0 and 1
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Compatibility {
+    pub score: f32,
+    pub shared_interests: Vec<String>,
+    pub interests: f32,
+    pub age: f32,
     pub distance: f32,
-    pub photo_urls: Option<String>,
-    pub photo_display_orders: Option<String>,
+    pub recency: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProfileDetails {
+    pub interests: Vec<String>,
+    pub languages: Vec<String>,
+    pub height: Option<u16>, // cm
+    pub job: Option<String>,
+    pub education: Option<String>,
+    pub prompts: Vec<ProfilePrompt>,
+}
+
+impl ProfileDetails {
+    // Reads the profile details of a Users row
+    pub fn from_row(row: &rusqlite::Row) -> rusqlite::Result<ProfileDetails> {
+        Ok(ProfileDetails {
+            interests: from_json(row, "interests")?,
+            languages: from_json(row, "languages")?,
+            height: row.get("height")?,
+            job: row.get("job")?,
+            education: row.get("education")?,
+            prompts: from_json(row, "prompts")?,
+        })
+    }
 }
 
+// Reads a column stored as JSON
+pub fn from_json<T: DeserializeOwned>(row: &rusqlite::Row, column: &str) -> rusqlite::Result<T> {
+    let json: String = row.get(column)?;
+    serde_json::from_str(&json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(
+            row.as_ref().column_index(column).unwrap_or_default(),
+            rusqlite::types::Type::Text,
+            Box::new(e),
+        )
+    })
+}
+
+pub struct Swipe {
+    pub match_uuid: String,
+    pub swiped: String,
+    pub love: u8, // 0 : pass, 1 : like, 2 : super like
+    pub swipe_datetime: String,
+}
+
+// A potential lover along with what the discovery ranking needs to know about them
+pub struct Candidate {
+    pub lover: PotentialLover,
+    pub features: RankingFeatures,
+}
+
+pub struct RankingFeatures {
+    pub distance: f32,
+    pub verified: bool,
+    pub likes_received: usize,
+    pub passes_received: usize,
+    pub super_liked_you: bool,
+}
+
+impl User {
+    // Where the user discovers from and is discovered at (radians) : the travel location until it expires,
+    // the home location otherwise
+    pub fn discovery_location(&self) -> (f32, f32) {
+        let travelling = self
+            .travel_expiry
+            .as_ref()
+            .and_then(|expiry| expiry.parse::<chrono::DateTime<chrono::Utc>>().ok())
+            .is_some_and(|expiry| expiry > chrono::offset::Utc::now());
+        match (self.travel_latitude, self.travel_longitude) {
+            (Some(latitude), Some(longitude)) if travelling => (latitude, longitude),
+            _ => (self.latitude, self.longitude),
+        }
+    }
+
+    // Location changes are rate limited, so that moving around can't be used to trilaterate other users
+    pub fn can_change_location(&self) -> bool {
+        self.location_updated_at
+            .parse::<chrono::DateTime<chrono::Utc>>()
+            .map_or(true, |updated_at| {
+                chrono::offset::Utc::now() - updated_at
+                    >= chrono::Duration::seconds(LOCATION_UPDATE_INTERVAL)
+            })
+    }
+}
+
+// What a user is looking for, and who the user is so that the search can be mutual.
+// Latitude and longitude are in radians
+pub struct DiscoveryCriteria {
+    pub user_uuid: String,
+    pub looking_for: Gender,
+    pub search_radius: u16,
+    pub latitude: f32,
+    pub longitude: f32,
+    pub age_min: u8,
+    pub age_max: u8,
+    pub gender: String,
+    pub age: u8,
+    pub recycle_passes: bool,
+    pub interests: Vec<String>,
+    pub filters: DiscoveryFilters,
+    pub soft_preferences: DiscoveryPreferences, // only used by the ranking
+}
+
+// Filters excluding potential lovers, built from the hard discovery preferences
+pub struct DiscoveryFilters {
+    has_photos: bool,
+    verified: bool,
+    last_active_days: Option<u16>,
+    height_min: Option<u16>,
+    height_max: Option<u16>,
+    interests: String, // JSON array, users sharing any of them
+    languages: String, // JSON array, users speaking any of them
+}
+
+impl DiscoveryFilters {
+    pub fn from_preferences(preferences: &DiscoveryPreferences) -> DiscoveryFilters {
+        let hard = preferences.only(FilterStrength::Hard);
+        DiscoveryFilters {
+            has_photos: hard.has_photos.is_some(),
+            verified: hard.verified.is_some(),
+            last_active_days: hard.last_active_within_days.map(|days| days.value),
+            height_min: hard.height.as_ref().map(|height| height.value.min),
+            height_max: hard.height.as_ref().map(|height| height.value.max),
+            interests: serde_json::to_string(&hard.interests.map_or(vec![], |i| i.value)).unwrap(),
+            languages: serde_json::to_string(&hard.languages.map_or(vec![], |l| l.value)).unwrap(),
+        }
+    }
+}
+
+impl DiscoveryCriteria {
+    pub fn from_user(
+        user: &User,
+        preferences: &DiscoveryPreferences,
+    ) -> Result<DiscoveryCriteria, SqliteError> {
+        let (latitude, longitude) = user.discovery_location();
+        Ok(DiscoveryCriteria {
+            user_uuid: user.uuid.clone(),
+            looking_for: user
+                .looking_for
+                .parse()
+                .map_err(|_| SqliteError::SqliteFailureNoText)?,
+            search_radius: user.search_radius,
+            latitude,
+            longitude,
+            age_min: user.looking_for_age_min,
+            age_max: user.looking_for_age_max,
+            gender: user.gender.clone(),
+            age: user.age,
+            recycle_passes: user.recycle_passed_profiles,
+            interests: user.profile.interests.clone(),
+            filters: DiscoveryFilters::from_preferences(preferences),
+            soft_preferences: preferences.only(FilterStrength::Soft),
+        })
+    }
+
+    pub fn bounding_box(&self) -> BoundingBox {
+        BoundingBox::around(self.latitude, self.longitude, self.search_radius as f32)
+    }
+
+    // Parameters used by DISTANCE and DISCOVERY_CONDITIONS, `bounding_box` comes from self.bounding_box()
+    pub fn named_params<'a>(
+        &'a self,
+        db: &'a AppState,
+        bounding_box: &'a BoundingBox,
+    ) -> Vec<(&'static str, &'a dyn ToSql)> {
+        vec![
+            (":user_uuid", &self.user_uuid),
+            (":latitude", &self.latitude),
+            (":longitude", &self.longitude),
+            (":looking_for", &self.looking_for),
+            (":age_min", &self.age_min),
+            (":age_max", &self.age_max),
+            (":search_radius", &self.search_radius),
+            (":mutual", &db.mutual_discovery),
+            (":recycle_passes", &self.recycle_passes),
+            (":pass_cooldown_days", &db.swipe_config.pass_cooldown_days),
+            (":gender", &self.gender),
+            (":age", &self.age),
+            (":min_latitude", &bounding_box.min_latitude),
+            (":max_latitude", &bounding_box.max_latitude),
+            (":min_longitude", &bounding_box.min_longitude),
+            (":max_longitude", &bounding_box.max_longitude),
+            (":interests", &self.filters.interests),
+            (":languages", &self.filters.languages),
+            (":height_min", &self.filters.height_min),
+            (":height_max", &self.filters.height_max),
+            (":has_photos", &self.filters.has_photos),
+            (
+                ":require_approved_photo",
+                &db.moderation_config.require_approved_photo,
+            ),
+            (":verified", &self.filters.verified),
+            (":last_active_days", &self.filters.last_active_days),
+        ]
+    }
+}
+
+// Distance in km between the searching user and the Users row, travelling users are at their travel location
+pub const DISTANCE: &str = "
+    6371 * acos(
+        sin(:latitude) * sin(COALESCE(Users.travel_latitude, Users.latitude)) +
+        cos(:latitude) * cos(COALESCE(Users.travel_latitude, Users.latitude))
+        * cos(:longitude - COALESCE(Users.travel_longitude, Users.longitude))
+    )";
+
+// Age in years of the Users row
+pub const AGE: &str = "
+    (
+        CAST(strftime('%Y', 'now') AS INTEGER) - CAST(strftime('%Y', Users.birthdate) AS INTEGER)
+        - (strftime('%m-%d', 'now') < strftime('%m-%d', Users.birthdate))
+    )";
+
+// Conditions shared by every discovery query, so that statistics stay consistent with the deck.
+// `distance` and `age` must be selected with DISTANCE and AGE
+pub const DISCOVERY_CONDITIONS: &str = "
+    Users.user_id IN ( -- cheap prefilter through the spatial index, the exact distance is checked below
+        SELECT user_id FROM UsersLocations
+        WHERE max_latitude >= :min_latitude AND min_latitude <= :max_latitude
+        AND max_longitude >= :min_longitude AND min_longitude <= :max_longitude
+    )
+    AND Users.user_uuid <> :user_uuid
+    AND Users.gender = :looking_for
+    AND age <= :age_max
+    AND age >= :age_min
+    AND Users.user_uuid NOT IN ( -- don't pick someone that the user has already swipped
+        SELECT swiped as user_uuid
+        FROM MatchingResults
+        WHERE swiper = :user_uuid AND undone = 0
+        AND NOT ( -- unless it was a first pass, old enough to be shown again
+            :recycle_passes = 1 AND love = 0 AND recycled = 0
+            AND julianday(swipe_datetime) < julianday('now', '-' || :pass_cooldown_days || ' days')
+        )
+    )
+    AND distance < :search_radius
+    AND ((:has_photos = 0 AND :require_approved_photo = 0) OR EXISTS (
+        SELECT 1 FROM Photos WHERE Photos.user_uuid = Users.user_uuid AND Photos.status = 'approved'
+    ))
+    AND (:verified = 0 OR Users.verified = 1)
+    AND (
+        :last_active_days IS NULL
+        OR julianday(Users.last_seen) > julianday('now', '-' || :last_active_days || ' days')
+    )
+    AND (:height_min IS NULL OR Users.height >= :height_min)
+    AND (:height_max IS NULL OR Users.height <= :height_max)
+    AND (json_array_length(:interests) = 0 OR EXISTS (
+        SELECT 1 FROM json_each(Users.interests)
+        WHERE value IN (SELECT value FROM json_each(:interests))
+    ))
+    AND (json_array_length(:languages) = 0 OR EXISTS (
+        SELECT 1 FROM json_each(Users.languages)
+        WHERE value IN (SELECT value FROM json_each(:languages))
+    ))
+    AND (:mutual = 0 OR ( -- the user must also be what the potential lover is looking for
+        Users.looking_for IN (:gender, 'any')
+        AND Users.looking_for_age_min <= :age
+        AND Users.looking_for_age_max >= :age
+        AND distance < Users.search_radius
+    ))";
+
 pub fn create_user(
     db: &Arc<AppState>,
     user: requests::CreateUserRequest,
 ) -> Result<(), SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
-            .prepare_cached("INSERT INTO Users (user_uuid, private_user_uuid, name, password, email, last_seen, age, latitude, longitude, gender, looking_for) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .prepare_cached("INSERT INTO Users (user_uuid, private_user_uuid, name, password, email, last_seen, birthdate, latitude, longitude, gender, looking_for) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
             .map_err(map_sqlite_error)?;
     statement
         .execute(params![
@@ -59,7 +349,7 @@ pub fn create_user(
             user.password,
             user.email,
             format!("{:?}", chrono::offset::Utc::now()), // Last seen = now
-            user.age,
+            user.birthdate,
             user.latitude * std::f32::consts::PI / 180.,
             user.longitude * std::f32::consts::PI / 180.,
             user.gender,
@@ -74,7 +364,9 @@ pub fn create_user(
 pub fn get_user_by_email(db: &Arc<AppState>, email: String) -> Result<User, SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
-        .prepare_cached("SELECT * FROM Users WHERE email = ? LIMIT 1")
+        .prepare_cached(&format!(
+            "SELECT *, {AGE} as age FROM Users WHERE email = ? LIMIT 1"
+        ))
         .map_err(map_sqlite_error)?;
 
     statement
@@ -86,6 +378,7 @@ pub fn get_user_by_email(db: &Arc<AppState>, email: String) -> Result<User, Sqli
                 email: row.get("email")?,
                 password: "Have fun with this password bro".to_string(),
                 last_seen: row.get("last_seen")?,
+                birthdate: row.get("birthdate")?,
                 age: row.get("age")?,
                 latitude: row.get("latitude")?,
                 longitude: row.get("longitude")?,
@@ -95,8 +388,17 @@ pub fn get_user_by_email(db: &Arc<AppState>, email: String) -> Result<User, Sqli
                 looking_for_age_min: row.get("looking_for_age_min")?,
                 looking_for_age_max: row.get("looking_for_age_max")?,
                 description: row.get("description")?,
-                photo_urls: Some("".to_string()),
-                photo_display_orders: Some("".to_string()),
+                recycle_passed_profiles: row.get("recycle_passed_profiles")?,
+                travel_latitude: row.get("travel_latitude")?,
+                travel_longitude: row.get("travel_longitude")?,
+                travel_label: row.get("travel_label")?,
+                travel_expiry: row.get("travel_expiry")?,
+                location_offset_north: row.get("location_offset_north")?,
+                location_offset_east: row.get("location_offset_east")?,
+                location_updated_at: row.get("location_updated_at")?,
+                profile: ProfileDetails::from_row(row)?,
+                photos: Vec::new(),
+                legacy_photos: None,
             })
         })
         .map_err(map_sqlite_error)
@@ -155,18 +457,16 @@ pub fn get_user_uuid_by_private_uuid(
 pub fn get_user_by_uuid(db: &Arc<AppState>, user_uuid: String) -> Result<User, SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
-        .prepare_cached(
+        .prepare_cached(&format!(
             "
         SELECT *,
-        GROUP_CONCAT(Photos.url, ',') as photo_urls,
-        GROUP_CONCAT(Photos.display_order, ',') as photo_display_orders
-        
+        {AGE} as age,
+        {OWN_PHOTOS} as photos
         FROM Users 
-        LEFT JOIN Photos ON Users.user_uuid = Photos.user_uuid 
         WHERE Users.user_uuid = ?
         LIMIT 1
-        ",
-        )
+        "
+        ))
         .map_err(map_sqlite_error)?;
     statement
         .query_row(params![user_uuid], |row| {
@@ -177,6 +477,7 @@ pub fn get_user_by_uuid(db: &Arc<AppState>, user_uuid: String) -> Result<User, S
                 password: "".to_string(), // todo : fix
                 email: row.get("email")?,
                 last_seen: row.get("last_seen")?,
+                birthdate: row.get("birthdate")?,
                 age: row.get("age")?,
                 latitude: row.get("latitude")?,
                 longitude: row.get("longitude")?,
@@ -186,8 +487,17 @@ pub fn get_user_by_uuid(db: &Arc<AppState>, user_uuid: String) -> Result<User, S
                 looking_for_age_min: row.get("looking_for_age_min")?,
                 looking_for_age_max: row.get("looking_for_age_max")?,
                 description: row.get("description")?,
-                photo_urls: row.get("photo_urls")?,
-                photo_display_orders: row.get("photo_display_orders")?,
+                recycle_passed_profiles: row.get("recycle_passed_profiles")?,
+                travel_latitude: row.get("travel_latitude")?,
+                travel_longitude: row.get("travel_longitude")?,
+                travel_label: row.get("travel_label")?,
+                travel_expiry: row.get("travel_expiry")?,
+                location_offset_north: row.get("location_offset_north")?,
+                location_offset_east: row.get("location_offset_east")?,
+                location_updated_at: row.get("location_updated_at")?,
+                profile: ProfileDetails::from_row(row)?,
+                photos: from_json(row, "photos")?,
+                legacy_photos: None,
             })
         })
         .map_err(map_sqlite_error)
@@ -206,9 +516,12 @@ pub fn delete_user_by_uuid(db: &Arc<AppState>, user_uuid: String) -> Result<(),
     Ok(())
 }
 
+// The location must already carry `location_offset`, if any
 pub fn update_user_infos(
     db: &Arc<AppState>,
     user: requests::UpdateUserInfosReq,
+    location_offset: Option<(f32, f32)>,
+    location_updated_at: String,
 ) -> Result<(), SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
@@ -216,7 +529,6 @@ pub fn update_user_infos(
             "UPDATE Users
                 SET name = ?,
                 email = ?,
-                age = ?,
                 last_seen = ?,
                 latitude = ?,
                 longitude = ?,
@@ -225,7 +537,11 @@ pub fn update_user_infos(
                 search_radius = ?,
                 looking_for_age_min = ?,
                 looking_for_age_max = ?,
-                description = ?
+                description = ?,
+                recycle_passed_profiles = ?,
+                location_offset_north = ?,
+                location_offset_east = ?,
+                location_updated_at = ?
                 WHERE user_uuid = ?",
         )
         .map_err(map_sqlite_error)?;
@@ -234,7 +550,6 @@ pub fn update_user_infos(
         .execute(params![
             user.name,
             user.email,
-            user.age,
             format!("{:?}", chrono::offset::Utc::now()), // Last seen = now
             user.latitude,
             user.longitude,
@@ -244,6 +559,10 @@ pub fn update_user_infos(
             user.looking_for_age_min,
             user.looking_for_age_max,
             user.description,
+            user.recycle_passed_profiles,
+            location_offset.map(|(north, _)| north),
+            location_offset.map(|(_, east)| east),
+            location_updated_at,
             user.uuid
         ])
         .map_err(map_sqlite_error)?;
@@ -271,103 +590,440 @@ pub fn update_user_last_seen(db: &Arc<AppState>, user_uuid: String) -> Result<()
     Ok(())
 }
 
-pub fn find_love_target(
+pub fn update_profile_details(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    profile: requests::UpdateProfileRequest,
+) -> Result<(), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "UPDATE Users
+                SET interests = ?,
+                languages = ?,
+                height = ?,
+                job = ?,
+                education = ?,
+                prompts = ?
+                WHERE user_uuid = ?",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement
+        .execute(params![
+            serde_json::to_string(&profile.interests).unwrap(),
+            serde_json::to_string(&profile.languages).unwrap(),
+            profile.height,
+            profile.job,
+            profile.education,
+            serde_json::to_string(&profile.prompts).unwrap(),
+            user_uuid
+        ])
+        .map_err(map_sqlite_error)?;
+
+    Ok(())
+}
+
+// Latitude and longitude are in radians, expiry is UTC ISO8601
+pub fn set_travel(
     db: &Arc<AppState>,
     user_uuid: String,
-    looking_for: String,
-    search_radius: u16,
     latitude: f32,
     longitude: f32,
-    age_min: u8,
-    age_max: u8,
-) -> Result<PotentialLover, SqliteError> {
+    label: String,
+    expiry: String,
+) -> Result<(), SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
         .prepare_cached(
-            "
-                SELECT *, 
-                6371 * acos(
-                    sin(?) * sin(latitude) +
-                    cos(?) * cos(latitude) * cos(? - longitude)
-                ) as distance,
-                GROUP_CONCAT(Photos.url, ',') as photo_urls,
-                GROUP_CONCAT(Photos.display_order, ',') as photo_display_orders
-
-                FROM Users
-                LEFT JOIN Photos ON Users.user_uuid = Photos.user_uuid 
-                WHERE Users.user_uuid <> ?
-                AND Users.gender = ?
-                AND Users.age <= ?
-                AND Users.age >= ?
-                AND Users.user_uuid NOT IN ( -- don't pick someone that the user has already swipped
-                    SELECT swiped as user_uuid
-                    FROM MatchingResults
-                    WHERE swiper = ?
-                )
-                AND distance < ?
-                ORDER BY datetime(Users.last_seen) DESC -- Getting the most recently active user
-                LIMIT 1
-               ",
+            "UPDATE Users
+                SET travel_latitude = ?,
+                travel_longitude = ?,
+                travel_label = ?,
+                travel_expiry = ?,
+                location_updated_at = ?
+                WHERE user_uuid = ?",
         )
         .map_err(map_sqlite_error)?;
 
-    let result = statement.query_row(
-        params![
-            latitude,
+    statement
+        .execute(params![
             latitude,
             longitude,
-            user_uuid,
-            looking_for,
-            age_max,
-            age_min,
-            user_uuid,
-            search_radius
-        ],
-        |row| {
-            Ok(PotentialLover {
-                uuid: row.get("user_uuid")?,
-                name: row.get("name")?,
-                last_seen: row.get("last_seen")?,
-                age: row.get("age")?,
-                distance: row.get("distance")?,
-                gender: row.get("gender")?,
-                description: row.get("description")?,
-                photo_urls: row.get("photo_urls")?,
-                photo_display_orders: row.get("photo_display_orders")?,
+            label,
+            expiry,
+            format!("{:?}", chrono::offset::Utc::now()),
+            user_uuid
+        ])
+        .map_err(map_sqlite_error)?;
+
+    Ok(())
+}
+
+pub fn clear_travel(db: &Arc<AppState>, user_uuid: String) -> Result<(), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "UPDATE Users
+                SET travel_latitude = NULL,
+                travel_longitude = NULL,
+                travel_label = NULL,
+                travel_expiry = NULL
+                WHERE user_uuid = ?",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement
+        .execute(params![user_uuid])
+        .map_err(map_sqlite_error)?;
+
+    Ok(())
+}
+
+// Sends every user whose travel has expired back to their home location, returns how many were
+pub fn clear_expired_travels(db: &Arc<AppState>) -> Result<usize, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "UPDATE Users
+                SET travel_latitude = NULL,
+                travel_longitude = NULL,
+                travel_label = NULL,
+                travel_expiry = NULL
+                WHERE julianday(travel_expiry) <= julianday('now')",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement.execute([]).map_err(map_sqlite_error)
+}
+
+// Candidates matching DISCOVERY_CONDITIONS with their ranking features, see find_love_targets
+fn love_targets_query() -> String {
+    format!(
+        "
+            SELECT *,
+            {DISTANCE} as distance,
+            {AGE} as age,
+            {PHOTOS} as photos,
+            (
+                SELECT COUNT(*) FROM MatchingResults
+                WHERE swiped = Users.user_uuid AND love >= 1 AND undone = 0
+            ) as likes_received,
+            (
+                SELECT COUNT(*) FROM MatchingResults
+                WHERE swiped = Users.user_uuid AND love = 0 AND undone = 0
+            ) as passes_received,
+            EXISTS (
+                SELECT 1 FROM MatchingResults
+                WHERE swiper = Users.user_uuid AND swiped = :user_uuid AND love >= 1 AND undone = 0
+            ) as liked_you,
+            EXISTS (
+                SELECT 1 FROM MatchingResults
+                WHERE swiper = Users.user_uuid AND swiped = :user_uuid AND love = 2 AND undone = 0
+            ) as super_liked_you
+
+            FROM Users
+            WHERE {DISCOVERY_CONDITIONS}
+            ORDER BY datetime(Users.last_seen) DESC -- Getting the most recently active users
+            LIMIT :limit
+        "
+    )
+}
+
+// Get up to `limit` potential lovers, the most recently active users first.
+// This is only a preselection, candidates are then ordered by the discovery ranking
+pub fn find_love_targets(
+    db: &Arc<AppState>,
+    criteria: &DiscoveryCriteria,
+    limit: usize,
+) -> Result<Vec<Candidate>, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(&love_targets_query())
+        .map_err(map_sqlite_error)?;
+
+    let bounding_box = criteria.bounding_box();
+    let mut params = criteria.named_params(db, &bounding_box);
+    params.push((":limit", &limit));
+    let result_rows = statement
+        .query_map(params.as_slice(), |row| {
+            Ok(Candidate {
+                lover: PotentialLover {
+                    uuid: row.get("user_uuid")?,
+                    name: row.get("name")?,
+                    last_seen: row.get("last_seen")?,
+                    age: row.get("age")?,
+                    distance: distance_label(row.get("distance")?),
+                    gender: row.get("gender")?,
+                    description: row.get("description")?,
+                    photos: from_json(row, "photos")?,
+                    legacy_photos: None,
+                    liked_you: row.get("liked_you")?,
+                    travelling_to: row.get("travel_label")?,
+                    profile: ProfileDetails::from_row(row)?,
+                    compatibility: Compatibility::default(),
+                },
+                features: RankingFeatures {
+                    distance: row.get("distance")?,
+                    verified: row.get("verified")?,
+                    likes_received: row.get("likes_received")?,
+                    passes_received: row.get("passes_received")?,
+                    super_liked_you: row.get("super_liked_you")?,
+                },
             })
-        },
-    );
-    // Do no use ? to handle error here :
-    // Because of the group_concat, when there is no user found, we will still get a row with null
-    // value, and then when trying to access values "row.get("user_uuid")?," we will get an error that
-    // is different than just "QueryReturnedNoRows" : InvalidColumnType(1, "user_uuid", Null)
-    match result {
-        Ok(potential_lover) => Ok(potential_lover),
-        Err(rusqlite::Error::InvalidColumnType(_, _, _)) => Err(SqliteError::NotFound),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Err(SqliteError::NotFound),
-        Err(_) => Err(SqliteError::UnknownSqliteProblem),
+        })
+        .map_err(map_sqlite_error)?;
+
+    let mut candidates = Vec::new();
+    for candidate in result_rows {
+        candidates.push(candidate.map_err(map_sqlite_error)?);
     }
+
+    Ok(candidates)
 }
 
+// A first pass old enough to be shown again can be swiped a second time, this second swipe is permanent.
+// An undone swipe can be swiped again, a recycled pass stays recycled
 pub fn swipe_user(
     db: &Arc<AppState>,
     swiper: String,
     swiped: String,
-    love: u8, // 0 : swiper dont like swiped, 1 : swiper like swiped
+    kind: SwipeKind,
 ) -> Result<(), SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
         .prepare_cached(
-            "INSERT INTO MatchingResults (match_uuid, swiper, swiped, love) VALUES (?, ?, ?, ?)",
+            "
+            INSERT INTO MatchingResults (match_uuid, swiper, swiped, love, swipe_datetime) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (swiper, swiped) DO UPDATE SET
+                love = excluded.love,
+                swipe_datetime = excluded.swipe_datetime,
+                recycled = MatchingResults.recycled OR MatchingResults.undone = 0,
+                undone = 0
+            WHERE MatchingResults.undone = 1 OR (
+                MatchingResults.love = 0 AND MatchingResults.recycled = 0
+                AND julianday(MatchingResults.swipe_datetime) < julianday('now', '-' || ? || ' days')
+            )
+            ",
+        )
+        .map_err(map_sqlite_error)?;
+    let swipes_count = statement
+        .execute(params![
+            Uuid::now_v7().to_string(),
+            swiper,
+            swiped,
+            kind,
+            format!("{:?}", chrono::offset::Utc::now()),
+            db.swipe_config.pass_cooldown_days
+        ])
+        .map_err(map_sqlite_error)?;
+
+    // The user was already swiped and can't be swiped again
+    if swipes_count == 0 {
+        return Err(SqliteError::Conflict);
+    }
+
+    Ok(())
+}
+
+pub fn get_last_swipe(db: &Arc<AppState>, swiper: String) -> Result<Swipe, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "
+            SELECT * FROM MatchingResults
+            WHERE swiper = ? AND undone = 0
+            ORDER BY julianday(swipe_datetime) DESC
+            LIMIT 1
+            ",
         )
         .map_err(map_sqlite_error)?;
+
     statement
-        .execute(params![Uuid::now_v7().to_string(), swiper, swiped, love,])
+        .query_row(params![swiper], |row| {
+            Ok(Swipe {
+                match_uuid: row.get("match_uuid")?,
+                swiped: row.get("swiped")?,
+                love: row.get("love")?,
+                swipe_datetime: row.get("swipe_datetime")?,
+            })
+        })
+        .map_err(map_sqlite_error)
+}
+
+// Mark the swipe undone and remove the love relation it created if any, then record the undo for the quota.
+// The swipe is kept rather than deleted : its pass history decides whether the user can be recycled
+pub fn undo_swipe(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    swipe: &Swipe,
+    love_uuid: Option<String>,
+) -> Result<(), SqliteError> {
+    let mut binding = db.connection.get().unwrap();
+    let tx = binding.transaction().map_err(map_sqlite_error)?;
+    tx.prepare_cached("UPDATE MatchingResults SET undone = 1 WHERE match_uuid = ?")
+        .map_err(map_sqlite_error)?
+        .execute(params![swipe.match_uuid])
         .map_err(map_sqlite_error)?;
 
+    if let Some(love_uuid) = love_uuid {
+        tx.prepare_cached("DELETE FROM Lovers WHERE love_uuid = ?")
+            .map_err(map_sqlite_error)?
+            .execute(params![love_uuid])
+            .map_err(map_sqlite_error)?;
+    }
+
+    tx.prepare_cached(
+        "INSERT INTO SwipeUndos (undo_uuid, user_uuid, swiped, undo_datetime, love) VALUES (?, ?, ?, ?, ?)",
+    )
+    .map_err(map_sqlite_error)?
+    .execute(params![
+        Uuid::now_v7().to_string(),
+        user_uuid,
+        swipe.swiped,
+        format!("{:?}", chrono::offset::Utc::now()),
+        swipe.love
+    ])
+    .map_err(map_sqlite_error)?;
+
+    tx.commit().map_err(map_sqlite_error)?;
+
     Ok(())
 }
 
+// How many swipes the user made in the last 24 hours, and when the oldest of them leaves that window.
+// An undone swipe still counts, through its undo since the swipe itself may be swiped again
+pub fn swipes_last_day(
+    db: &Arc<AppState>,
+    user_uuid: String,
+) -> Result<(usize, Option<String>), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "
+            SELECT COUNT(*) as count,
+            strftime('%Y-%m-%dT%H:%M:%fZ', MIN(julianday(datetime)), '+1 day') as window_end
+            FROM (
+                SELECT swipe_datetime as datetime FROM MatchingResults
+                WHERE swiper = :user_uuid AND undone = 0
+                AND julianday(swipe_datetime) > julianday('now', '-1 day')
+                UNION ALL
+                SELECT undo_datetime as datetime FROM SwipeUndos
+                WHERE user_uuid = :user_uuid
+                AND julianday(undo_datetime) > julianday('now', '-1 day')
+            )
+            ",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement
+        .query_row(named_params! {":user_uuid": user_uuid}, |row| {
+            Ok((row.get("count")?, row.get("window_end")?))
+        })
+        .map_err(map_sqlite_error)
+}
+
+// How many swipes the user made in the last `seconds` seconds
+pub fn swipes_since_count(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    seconds: u32,
+) -> Result<usize, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "
+            SELECT COUNT(*) as count
+            FROM MatchingResults
+            WHERE swiper = ?
+            AND julianday(swipe_datetime) > julianday('now', '-' || ? || ' seconds')
+            ",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement
+        .query_row(params![user_uuid, seconds], |row| row.get("count"))
+        .map_err(map_sqlite_error)
+}
+
+// Among the last `sample` swipes of the user, how many swipes there are and how many are likes
+pub fn recent_likes_count(
+    db: &Arc<AppState>,
+    user_uuid: String,
+    sample: usize,
+) -> Result<(usize, usize), SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "
+            SELECT COUNT(*) as count, COALESCE(SUM(love >= 1), 0) as likes
+            FROM (
+                SELECT love FROM MatchingResults
+                WHERE swiper = ? AND undone = 0
+                ORDER BY julianday(swipe_datetime) DESC
+                LIMIT ?
+            )
+            ",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement
+        .query_row(params![user_uuid, sample], |row| {
+            Ok((row.get("count")?, row.get("likes")?))
+        })
+        .map_err(map_sqlite_error)
+}
+
+// How many super likes the user sent in the last 24 hours. Like in swipes_last_day, an undone super
+// like still counts through its undo, since its swipe may be overwritten by a new one
+pub fn super_likes_last_day_count(
+    db: &Arc<AppState>,
+    user_uuid: String,
+) -> Result<usize, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "
+            SELECT
+            (
+                SELECT COUNT(*) FROM MatchingResults
+                WHERE swiper = :user_uuid AND love = 2 AND undone = 0
+                AND julianday(swipe_datetime) > julianday('now', '-1 day')
+            ) + (
+                SELECT COUNT(*) FROM SwipeUndos
+                WHERE user_uuid = :user_uuid AND love = 2
+                AND julianday(undo_datetime) > julianday('now', '-1 day')
+            ) as count
+            ",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement
+        .query_row(named_params! {":user_uuid": user_uuid}, |row| {
+            row.get("count")
+        })
+        .map_err(map_sqlite_error)
+}
+
+// How many swipes the user undid in the last 24 hours
+pub fn undos_last_day_count(db: &Arc<AppState>, user_uuid: String) -> Result<usize, SqliteError> {
+    let binding = db.connection.get().unwrap();
+    let mut statement = binding
+        .prepare_cached(
+            "
+            SELECT COUNT(*) as count
+            FROM SwipeUndos
+            WHERE user_uuid = ?
+            AND julianday(undo_datetime) > julianday('now', '-1 day')
+            ",
+        )
+        .map_err(map_sqlite_error)?;
+
+    statement
+        .query_row(params![user_uuid], |row| row.get("count"))
+        .map_err(map_sqlite_error)
+}
+
 pub fn check_mutual_love(
     db: &Arc<AppState>,
     lover1: String,
@@ -379,8 +1035,8 @@ pub fn check_mutual_love(
             "
             SELECT COUNT(*) as count 
             FROM MatchingResults
-            WHERE (swiper = ? AND swiped = ? AND love = 1) 
-            OR (swiper = ? AND swiped = ? AND love = 1)",
+            WHERE (swiper = ? AND swiped = ? AND love >= 1 AND undone = 0)
+            OR (swiper = ? AND swiped = ? AND love >= 1 AND undone = 0)",
         )
         .map_err(map_sqlite_error)?;
     let mutual_love_count: usize = statement
@@ -395,7 +1051,7 @@ pub fn check_mutual_love(
 pub fn swiped_count(
     db: &Arc<AppState>,
     user_uuid: String,
-    loved: u8,
+    kind: SwipeKind,
 ) -> Result<usize, SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
@@ -403,12 +1059,12 @@ pub fn swiped_count(
             "
             SELECT COUNT(*) as count 
             FROM MatchingResults
-            WHERE swiped = ? AND love = ?
+            WHERE swiped = ? AND love = ? AND undone = 0
             ",
         )
         .map_err(map_sqlite_error)?;
     let swiped_count: usize = statement
-        .query_row(params![user_uuid, loved], |row| row.get("count"))
+        .query_row(params![user_uuid, kind], |row| row.get("count"))
         .map_err(map_sqlite_error)?;
 
     Ok(swiped_count)
@@ -417,7 +1073,7 @@ pub fn swiped_count(
 pub fn swiping_count(
     db: &Arc<AppState>,
     user_uuid: String,
-    loved: u8,
+    kind: SwipeKind,
 ) -> Result<usize, SqliteError> {
     let binding = db.connection.get().unwrap();
     let mut statement = binding
@@ -425,13 +1081,373 @@ pub fn swiping_count(
             "
             SELECT COUNT(*) as count
             FROM MatchingResults
-            WHERE swiper = ? AND love = ?
+            WHERE swiper = ? AND love = ? AND undone = 0
             ",
         )
         .map_err(map_sqlite_error)?;
     let swiping_count: usize = statement
-        .query_row(params![user_uuid, loved], |row| row.get("count"))
+        .query_row(params![user_uuid, kind], |row| row.get("count"))
         .map_err(map_sqlite_error)?;
 
     Ok(swiping_count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access_layer::lover_dal;
+    use crate::utilities::test_fixtures::{
+        app_state, app_state_with, insert_user, paris_east, UserFixture,
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    // Ann searches men aged 25 to 45 within 50 km, every other user fails one side of the search
+    // at most. Returns the uuid of each user by name
+    fn seed_mutual_dataset(state: &Arc<AppState>) -> HashMap<&'static str, String> {
+        let man = |name, birthdate, km, looking_for, search_radius, age_min, age_max| UserFixture {
+            name,
+            birthdate,
+            longitude: paris_east(km),
+            gender: "male",
+            looking_for,
+            search_radius,
+            age_min,
+            age_max,
+            ..Default::default()
+        };
+        let users = [
+            UserFixture {
+                age_min: 25,
+                age_max: 45,
+                ..Default::default()
+            },
+            man("Bob", "1988-05-01", 5., "female", 50, 30, 40), // both sides match
+            man("Hal", "1985-05-01", 5., "any", 50, 18, 127),   // both sides match
+            man("Carl", "1988-05-01", 5., "male", 50, 18, 127), // not looking for women
+            man("Dan", "1995-05-01", 5., "female", 50, 18, 30), // Ann is too old for him
+            man("Ed", "1988-05-01", 20., "female", 5, 18, 127), // Ann is out of his radius
+            man("Fred", "1960-05-01", 5., "female", 50, 18, 127), // too old for Ann
+            man("Gus", "1988-05-01", 100., "female", 500, 18, 127), // Ann is out of her radius
+            UserFixture {
+                name: "Iris", // not a man
+                looking_for: "female",
+                ..Default::default()
+            },
+        ];
+        users
+            .into_iter()
+            .map(|user| (user.name, insert_user(state, user)))
+            .collect()
+    }
+
+    // Names of the users discovered by user_uuid and the matching potential count
+    fn discover(state: &Arc<AppState>, user_uuid: &str) -> (Vec<String>, usize) {
+        let user = get_user_by_uuid(state, user_uuid.to_string()).unwrap();
+        let criteria =
+            DiscoveryCriteria::from_user(&user, &DiscoveryPreferences::default()).unwrap();
+        let mut names = find_love_targets(state, &criteria, 100)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| candidate.lover.name)
+            .collect::<Vec<String>>();
+        names.sort();
+        let count = lover_dal::potential_matches_count(state, &criteria).unwrap();
+        (names, count)
+    }
+
+    #[tokio::test]
+    async fn mutual_discovery_honours_both_users_preferences() {
+        let state = app_state().await;
+        let users = seed_mutual_dataset(&state);
+
+        let (names, count) = discover(&state, &users["Ann"]);
+        assert_eq!(names, vec!["Bob", "Hal"]);
+        assert_eq!(count, names.len());
+    }
+
+    #[tokio::test]
+    async fn mutual_discovery_works_in_both_directions() {
+        let state = app_state().await;
+        let users = seed_mutual_dataset(&state);
+
+        // Bob matches Ann's preferences so he finds her, those rejected by them don't
+        let (names, _) = discover(&state, &users["Bob"]);
+        assert!(names.contains(&"Ann".to_string()));
+        for name in ["Fred", "Gus"] {
+            let (names, count) = discover(&state, &users[name]);
+            assert!(!names.contains(&"Ann".to_string()), "{name} discovers Ann");
+            assert_eq!(count, names.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn one_sided_discovery_only_uses_the_searching_user_preferences() {
+        let state = app_state_with(|config| config.mutual_discovery = false).await;
+        let users = seed_mutual_dataset(&state);
+
+        let (names, count) = discover(&state, &users["Ann"]);
+        assert_eq!(names, vec!["Bob", "Carl", "Dan", "Ed", "Hal"]);
+        assert_eq!(count, names.len());
+    }
+
+    fn undo_last_swipe(state: &Arc<AppState>, swiper: &str) {
+        let swipe = get_last_swipe(state, swiper.to_string()).unwrap();
+        undo_swipe(state, swiper.to_string(), &swipe, None).unwrap();
+    }
+
+    #[tokio::test]
+    async fn an_undone_swipe_keeps_its_daily_slot() {
+        let state = app_state().await;
+        let users = seed_mutual_dataset(&state);
+        let (ann, bob) = (users["Ann"].clone(), users["Bob"].clone());
+
+        swipe_user(&state, ann.clone(), bob.clone(), SwipeKind::Pass).unwrap();
+        undo_last_swipe(&state, &ann);
+        assert_eq!(swipes_last_day(&state, ann.clone()).unwrap().0, 1);
+        // Bob is discovered and can be swiped again
+        assert!(discover(&state, &ann).0.contains(&"Bob".to_string()));
+        swipe_user(&state, ann.clone(), bob.clone(), SwipeKind::Like).unwrap();
+        assert_eq!(swipes_last_day(&state, ann.clone()).unwrap().0, 2);
+        assert_eq!(get_last_swipe(&state, ann).unwrap().swiped, bob);
+    }
+
+    #[tokio::test]
+    async fn an_undone_super_like_keeps_counting_in_its_quota() {
+        let state = app_state().await;
+        let users = seed_mutual_dataset(&state);
+        let (ann, bob) = (users["Ann"].clone(), users["Bob"].clone());
+
+        swipe_user(&state, ann.clone(), bob.clone(), SwipeKind::SuperLike).unwrap();
+        undo_last_swipe(&state, &ann);
+        assert_eq!(super_likes_last_day_count(&state, ann.clone()).unwrap(), 1);
+        // Swiping again overwrites the super like, its undo still counts
+        swipe_user(&state, ann.clone(), bob.clone(), SwipeKind::Pass).unwrap();
+        assert_eq!(super_likes_last_day_count(&state, ann.clone()).unwrap(), 1);
+        undo_last_swipe(&state, &ann);
+        swipe_user(&state, ann.clone(), bob, SwipeKind::SuperLike).unwrap();
+        assert_eq!(super_likes_last_day_count(&state, ann).unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn undoing_a_recycled_pass_keeps_it_recycled() {
+        let state = app_state().await;
+        let users = seed_mutual_dataset(&state);
+        let (ann, bob) = (users["Ann"].clone(), users["Bob"].clone());
+
+        swipe_user(&state, ann.clone(), bob.clone(), SwipeKind::Pass).unwrap();
+        state
+            .connection
+            .get()
+            .unwrap()
+            .execute(
+                "UPDATE MatchingResults SET swipe_datetime = '2000-01-01T00:00:00Z'",
+                [],
+            )
+            .unwrap();
+        swipe_user(&state, ann.clone(), bob.clone(), SwipeKind::Pass).unwrap();
+        undo_last_swipe(&state, &ann);
+        swipe_user(&state, ann.clone(), bob.clone(), SwipeKind::Pass).unwrap();
+
+        // The second pass is permanent, as if it had never been undone
+        assert!(!discover(&state, &ann).0.contains(&"Bob".to_string()));
+        assert!(matches!(
+            swipe_user(&state, ann, bob, SwipeKind::Like),
+            Err(SqliteError::Conflict)
+        ));
+    }
+
+    // Users spread around each location, the searching user included, with nothing but the distance
+    // excluding them from their search
+    fn seed_around(state: &Arc<AppState>, locations: &[(f32, f32)], users_per_location: usize) {
+        let mut rng = StdRng::seed_from_u64(34);
+        for (latitude, longitude) in locations {
+            for _ in 0..users_per_location {
+                let mut latitude = latitude + rng.gen_range(-1.5..1.5);
+                let mut longitude =
+                    longitude + rng.gen_range(-1.5..1.5) / latitude.to_radians().cos().max(0.05);
+                if latitude.abs() > 90. {
+                    // Past the pole, on the other side of the earth
+                    latitude = latitude.signum() * 180. - latitude;
+                    longitude += 180.;
+                }
+                longitude = (longitude + 540.) % 360. - 180.;
+                insert_user(
+                    state,
+                    UserFixture {
+                        name: "Bob",
+                        latitude,
+                        longitude,
+                        gender: "male",
+                        looking_for: "female",
+                        search_radius: 20000,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+
+    // The users closer than the search radius, computed over every user without the spatial prefilter
+    fn users_within_radius(state: &Arc<AppState>, criteria: &DiscoveryCriteria) -> Vec<String> {
+        let binding = state.connection.get().unwrap();
+        let mut statement = binding
+            .prepare(&format!(
+                "SELECT user_uuid FROM Users WHERE user_uuid <> :user_uuid AND {DISTANCE} < :search_radius"
+            ))
+            .unwrap();
+        let mut user_uuids = statement
+            .query_map(
+                named_params! {
+                    ":user_uuid": criteria.user_uuid,
+                    ":latitude": criteria.latitude,
+                    ":longitude": criteria.longitude,
+                    ":search_radius": criteria.search_radius,
+                },
+                |row| row.get(0),
+            )
+            .unwrap()
+            .collect::<Result<Vec<String>, _>>()
+            .unwrap();
+        user_uuids.sort();
+        user_uuids
+    }
+
+    #[tokio::test]
+    async fn spatial_prefilter_keeps_the_exact_distance_results() {
+        let state = app_state().await;
+        // Paris, the equator, the north pole and the antimeridian
+        let locations = [(48.8566, 2.3522), (0., 0.), (89.5, 30.), (-17.7, 179.9)];
+        seed_around(&state, &locations, 200);
+
+        for (latitude, longitude) in locations {
+            for search_radius in [10, 100, 200] {
+                let user_uuid = insert_user(
+                    &state,
+                    UserFixture {
+                        latitude,
+                        longitude,
+                        search_radius,
+                        ..Default::default()
+                    },
+                );
+                let user = get_user_by_uuid(&state, user_uuid).unwrap();
+                let criteria =
+                    DiscoveryCriteria::from_user(&user, &DiscoveryPreferences::default()).unwrap();
+
+                let mut found = find_love_targets(&state, &criteria, 10000)
+                    .unwrap()
+                    .into_iter()
+                    .map(|candidate| candidate.lover.uuid)
+                    .collect::<Vec<String>>();
+                found.sort();
+                let expected = users_within_radius(&state, &criteria);
+                assert!(!expected.is_empty() || search_radius == 10);
+                assert_eq!(
+                    found, expected,
+                    "around {latitude}, {longitude} within {search_radius} km"
+                );
+                assert_eq!(
+                    lover_dal::potential_matches_count(&state, &criteria).unwrap(),
+                    expected.len()
+                );
+
+                // Ann is removed so that she isn't found by the next searches
+                state
+                    .connection
+                    .get()
+                    .unwrap()
+                    .execute("DELETE FROM Users WHERE user_uuid = ?", [&user.uuid])
+                    .unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn discovery_reads_users_through_the_spatial_index() {
+        let state = app_state().await;
+        let users = seed_mutual_dataset(&state);
+        let user = get_user_by_uuid(&state, users["Ann"].clone()).unwrap();
+        let criteria =
+            DiscoveryCriteria::from_user(&user, &DiscoveryPreferences::default()).unwrap();
+
+        let binding = state.connection.get().unwrap();
+        let mut statement = binding
+            .prepare(&format!(
+                "EXPLAIN QUERY PLAN SELECT {DISTANCE} as distance, {AGE} as age FROM Users WHERE {DISCOVERY_CONDITIONS}"
+            ))
+            .unwrap();
+        let bounding_box = criteria.bounding_box();
+        let plan = statement
+            .query_map(
+                criteria.named_params(&state, &bounding_box).as_slice(),
+                |row| row.get::<_, String>("detail"),
+            )
+            .unwrap()
+            .collect::<Result<Vec<String>, _>>()
+            .unwrap();
+        // Users are only read by rowid, for the ids the R*Tree returns
+        assert!(plan.contains(&"SEARCH Users USING INTEGER PRIMARY KEY (rowid=?)".to_string()));
+        assert!(plan
+            .iter()
+            .any(|step| step.starts_with("SCAN UsersLocations VIRTUAL TABLE INDEX 2:")));
+        assert!(!plan.iter().any(|step| step.starts_with("SCAN Users ")));
+
+        // The swipes of each candidate are read through the indexes too, not scanned
+        let mut statement = binding
+            .prepare(&format!("EXPLAIN QUERY PLAN {}", love_targets_query()))
+            .unwrap();
+        let mut params = criteria.named_params(&state, &bounding_box);
+        params.push((":limit", &10));
+        let plan = statement
+            .query_map(params.as_slice(), |row| row.get::<_, String>("detail"))
+            .unwrap()
+            .collect::<Result<Vec<String>, _>>()
+            .unwrap();
+        assert!(!plan.iter().any(|step| step.starts_with("SCAN Users ")));
+        assert!(
+            !plan
+                .iter()
+                .any(|step| step.starts_with("SCAN MatchingResults")),
+            "{:?}",
+            plan
+        );
+    }
+
+    // Counts through DISCOVERY_CONDITIONS against a full scan computing every distance
+    // cargo test --release discovery_benchmark -- --ignored --nocapture
+    #[tokio::test]
+    #[ignore]
+    async fn discovery_benchmark() {
+        let state = app_state().await;
+        // 20000 users around Paris, Lyon, Berlin, Madrid and Rome
+        let cities = [
+            (48.8566, 2.3522),
+            (45.764, 4.8357),
+            (52.52, 13.405),
+            (40.4168, -3.7038),
+            (41.9028, 12.4964),
+        ];
+        seed_around(&state, &cities, 4000);
+        let user_uuid = insert_user(&state, UserFixture::default());
+        let user = get_user_by_uuid(&state, user_uuid).unwrap();
+        let criteria =
+            DiscoveryCriteria::from_user(&user, &DiscoveryPreferences::default()).unwrap();
+
+        let runs = 50;
+        let start = std::time::Instant::now();
+        for _ in 0..runs {
+            lover_dal::potential_matches_count(&state, &criteria).unwrap();
+        }
+        let indexed = start.elapsed() / runs;
+        let start = std::time::Instant::now();
+        for _ in 0..runs {
+            users_within_radius(&state, &criteria);
+        }
+        let scan = start.elapsed() / runs;
+        println!(
+            "{} users found, through the R*Tree : {indexed:?}, full scan : {scan:?}",
+            lover_dal::potential_matches_count(&state, &criteria).unwrap()
+        );
+    }
+}
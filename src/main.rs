@@ -10,7 +10,6 @@ mod service_layer;
 mod utilities;
 
 // TODO : Rework Actions CI/CD
-// TODO : Show when swiping if the user liked me already
 // TODO : Stats : How many people fit my criterion I havent swiped yet + How many people are looking for my type
 // TODO : Infos bulle (?) qui explique comment l'appli fonctionne, comment les stats fonctionnent
 // TODO : red dot sur activite swutcher nb new match
@@ -34,6 +33,7 @@ use axum::{
 };
 use std::net::SocketAddr;
 use tower_http::cors::CorsLayer;
+use tower_http::services::ServeDir;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -52,8 +52,32 @@ async fn main() {
     let config = configs::config::Config::new();
     let app_state = configs::app_state::AppState::new(&config).await;
     println!("config : {:?}", config);
+    // One-off maintenance commands, run instead of the server : cargo run -- <command>
+    match std::env::args().nth(1).as_deref() {
+        Some("reprocess-photos") => {
+            let delete_rejected = std::env::args().nth(2).as_deref() == Some("--delete");
+            service_layer::photos_service::reprocess_photos(&app_state, delete_rejected).await;
+            return;
+        }
+        Some("reconcile-photos") => {
+            let remove_orphans = std::env::args().nth(2).as_deref() == Some("--delete");
+            service_layer::photos_service::reconcile_photos(&app_state, remove_orphans).await;
+            return;
+        }
+        Some(command) => panic!("unknown command {}", command),
+        None => (),
+    }
+    tokio::spawn(service_layer::travel_service::expire_travels(
+        app_state.clone(),
+    ));
+    tokio::spawn(service_layer::discovery_service::expire_decks(
+        app_state.clone(),
+    ));
+    tokio::spawn(service_layer::photos_service::process_photo_deletions(
+        app_state.clone(),
+    ));
 
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/users", post(service_layer::user_service::create_user))
         .route(
             "/users/:user_uuid",
@@ -67,6 +91,18 @@ async fn main() {
             "/users/:user_uuid",
             delete(service_layer::user_service::delete_user),
         )
+        .route(
+            "/users/:user_uuid/profile",
+            put(service_layer::user_service::update_profile),
+        )
+        .route(
+            "/users/:user_uuid/travel",
+            put(service_layer::travel_service::set_travel),
+        )
+        .route(
+            "/users/:user_uuid/travel",
+            delete(service_layer::travel_service::cancel_travel),
+        )
         .route(
             "/users/findlover",
             get(service_layer::user_service::find_lover),
@@ -75,6 +111,22 @@ async fn main() {
             "/users/swipe",
             post(service_layer::user_service::swipe_user),
         )
+        .route(
+            "/swipes/undo",
+            post(service_layer::user_service::undo_swipe),
+        )
+        .route(
+            "/discovery/deck",
+            get(service_layer::discovery_service::get_deck),
+        )
+        .route(
+            "/discovery/preferences",
+            get(service_layer::discovery_service::get_preferences),
+        )
+        .route(
+            "/discovery/preferences",
+            put(service_layer::discovery_service::update_preferences),
+        )
         .route(
             "/users/:user_uuid/statistics/loved",
             get(service_layer::statistics_service::loved_count),
@@ -91,6 +143,14 @@ async fn main() {
             "/users/:user_uuid/statistics/rejecting",
             get(service_layer::statistics_service::rejecting_count),
         )
+        .route(
+            "/users/:user_uuid/statistics/super_loved",
+            get(service_layer::statistics_service::super_loved_count),
+        )
+        .route(
+            "/users/:user_uuid/statistics/super_loving",
+            get(service_layer::statistics_service::super_loving_count),
+        )
         .route(
             "/users/:user_uuid/statistics/matching_potential",
             get(service_layer::statistics_service::matching_potential),
@@ -124,10 +184,26 @@ async fn main() {
             "/photos/switch_photos",
             post(service_layer::photos_service::switch_photos),
         )
+        .route(
+            "/photos/order",
+            put(service_layer::photos_service::reorder_photos),
+        )
+        .route(
+            "/moderation/photos",
+            get(service_layer::moderation_service::get_pending_photos),
+        )
+        .route(
+            "/moderation/photos/:photo_uuid",
+            put(service_layer::moderation_service::review_photo),
+        )
         .route(
             "/lovers/:user_uuid",
             get(service_layer::lover_service::get_lovers),
         )
+        .route(
+            "/likes/received",
+            get(service_layer::lover_service::get_likes_received),
+        )
         .route(
             "/lovers/action/:love_uuid/tick_love",
             put(service_layer::lover_service::tick_love),
@@ -169,6 +245,7 @@ async fn main() {
                     http::header::AUTHORIZATION,
                     http::header::ACCEPT,
                     http::header::HeaderName::from_lowercase(b"trace").unwrap(),
+                    http::header::HeaderName::from_static(constants::constants::API_VERSION_HEADER),
                 ])
                 .allow_methods(vec![
                     Method::GET,
@@ -179,6 +256,12 @@ async fn main() {
                 ]),
         )
         .with_state(app_state.clone());
+    if config.storage.backend == configs::config::StorageBackend::Local {
+        app = app.nest_service(
+            constants::constants::LOCAL_STORAGE_ROUTE,
+            ServeDir::new(&config.storage.local_directory),
+        );
+    }
 
     let addr = SocketAddr::from((config.ip, config.port));
     println!("listening on {}", addr);
@@ -1,2 +1,3 @@
 pub mod service_errors;
 pub mod sqlite_errors;
+pub mod store_errors;
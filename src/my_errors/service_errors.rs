@@ -1,4 +1,6 @@
+use crate::constants::constants::LOCATION_UPDATE_INTERVAL;
 use crate::my_errors::sqlite_errors::SqliteError;
+use crate::responses::responses::SwipeQuota;
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
@@ -12,6 +14,8 @@ use std::ops::Add;
 pub struct ApiResponseError {
     pub error_message: String,
     pub error_code: ErrorCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>, // details a client can act on, example : the swipe quota
 }
 
 #[derive(Debug)]
@@ -23,6 +27,11 @@ pub enum ServiceError {
     ForbiddenQuery,
     ValueNotAccepted(String, String), // (Value, Reason)
     Transaction,
+    UndoNotAllowed(String), // Reason
+    SuperLikeQuotaExceeded,
+    SwipeQuotaExceeded(SwipeQuota),
+    LocationChangeTooFrequent,
+    ImageRejected(String), // Reason
     UnknownServiceProblem,
 }
 
@@ -46,10 +55,32 @@ impl ServiceError {
                 .add(" reason : ")
                 .add(reason),
             Self::Transaction => "Transaction error".to_string(),
+            Self::SuperLikeQuotaExceeded => "Daily super like quota exceeded".to_string(),
+            Self::SwipeQuotaExceeded(quota) => format!(
+                "Daily swipe quota exceeded, remaining : {}, reset at : {}",
+                quota.remaining,
+                quota.reset_at.as_deref().unwrap_or("now")
+            ),
+            Self::UndoNotAllowed(reason) => {
+                "Swipe cannot be undone, reason : ".to_string().add(reason)
+            }
+            Self::LocationChangeTooFrequent => format!(
+                "Location can only change once every {} minutes",
+                LOCATION_UPDATE_INTERVAL / 60
+            ),
+            Self::ImageRejected(reason) => "Image rejected, reason : ".to_string().add(reason),
             Self::UnknownServiceProblem => "Unknown service layer error".to_string(),
         }
     }
 
+    // Structured details sent along with the message
+    pub fn error_data(&self) -> Option<serde_json::Value> {
+        match self {
+            Self::SwipeQuotaExceeded(quota) => serde_json::to_value(quota).ok(),
+            _ => None,
+        }
+    }
+
     fn status_code(&self) -> StatusCode {
         match *self {
             Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
@@ -59,6 +90,11 @@ impl ServiceError {
             Self::ForbiddenQuery => StatusCode::FORBIDDEN,
             Self::ValueNotAccepted(_, _) => StatusCode::FORBIDDEN,
             Self::Transaction => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UndoNotAllowed(_) => StatusCode::FORBIDDEN,
+            Self::SuperLikeQuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+            Self::SwipeQuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::LocationChangeTooFrequent => StatusCode::TOO_MANY_REQUESTS,
+            Self::ImageRejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
             Self::UnknownServiceProblem => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -75,8 +111,13 @@ impl IntoResponse for ServiceError {
         let body = Json(ApiResponseError {
             error_message: self.error_message(),
             error_code: ErrorCode::UnspecifiedError, // TODO
+            data: self.error_data(),
         });
-        println!("service error encountered : {:?}", self);
+        match &self {
+            // Kept out of the response, only logged
+            Self::Sqlite(err) => println!("service error encountered : sqlite error {}", err),
+            _ => println!("service error encountered : {:?}", self),
+        }
 
         (http_status, body).into_response()
     }
@@ -4,6 +4,7 @@ use std::fmt;
 #[derive(Debug)]
 pub enum SqliteError {
     NotFound,
+    Conflict, // the row clashes with an existing one
     UnknownSqliteProblem,
     // SqliteFailure(libsqlite3_sys::Error),
     // SqliteFailureExplained(libsqlite3_sys::Error, String),
@@ -16,10 +17,20 @@ impl fmt::Display for SqliteError {
     }
 }
 
+// Extended result codes of sqlite, libsqlite3-sys keeps them private
+const SQLITE_CONSTRAINT_PRIMARYKEY: i32 = rusqlite::ffi::SQLITE_CONSTRAINT | (6 << 8);
+const SQLITE_CONSTRAINT_UNIQUE: i32 = rusqlite::ffi::SQLITE_CONSTRAINT | (8 << 8);
+
 pub fn map_sqlite_error(e: rusqlite::Error) -> SqliteError {
     println!("sqlite error encountered : {:?}", e);
     match e {
         rusqlite::Error::QueryReturnedNoRows => SqliteError::NotFound,
+        rusqlite::Error::SqliteFailure(failure, _)
+            if failure.extended_code == SQLITE_CONSTRAINT_UNIQUE
+                || failure.extended_code == SQLITE_CONSTRAINT_PRIMARYKEY =>
+        {
+            SqliteError::Conflict
+        }
         // rusqlite::Error::SqliteFailure(sqlite_failure_detail, Some(explaination)) => {
         //     SqliteError::SqliteFailureExplained(sqlite_failure_detail, explaination)
         // }
@@ -0,0 +1,26 @@
+use crate::my_errors::service_errors::ServiceError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum StoreError {
+    NotFound,
+    InvalidKey,
+    Backend(String), // error of the underlying storage
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::NotFound => write!(f, "object not found"),
+            StoreError::InvalidKey => write!(f, "invalid object key"),
+            StoreError::Backend(message) => write!(f, "storage backend error: {}", message),
+        }
+    }
+}
+
+impl From<StoreError> for ServiceError {
+    fn from(error: StoreError) -> Self {
+        println!("object store error encountered : {}", error);
+        ServiceError::Internal
+    }
+}
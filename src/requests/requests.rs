@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 // USERS //////////////////////////////////////
 #[derive(Serialize, Deserialize, Debug)]
@@ -7,7 +8,6 @@ pub struct UpdateUserInfosReq {
     pub name: String,
     pub password: String,
     pub email: String,
-    pub age: u8,
     pub latitude: f32,
     pub longitude: f32,
     pub gender: Gender,
@@ -16,6 +16,10 @@ pub struct UpdateUserInfosReq {
     pub looking_for_age_min: u8,
     pub looking_for_age_max: u8,
     pub description: String,
+    #[serde(default)] // not sent by older clients
+    pub recycle_passed_profiles: bool,
+    #[serde(default)]
+    pub fuzz_location: bool, // store the location with a fixed random offset
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
@@ -28,7 +32,21 @@ pub enum Gender {
     Any,
 }
 
-use rusqlite::{types::ToSqlOutput, ToSql};
+impl FromStr for Gender {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Gender, Self::Err> {
+        match input {
+            "male" => Ok(Gender::Male),
+            "female" => Ok(Gender::Female),
+            "any" => Ok(Gender::Any),
+            _ => Err(()),
+        }
+    }
+}
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::ToSql;
 
 impl ToSql for Gender {
     fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
@@ -45,7 +63,7 @@ pub struct CreateUserRequest {
     pub name: String,
     pub password: String,
     pub email: String,
-    pub age: u8,
+    pub birthdate: String, // ISO8601 date, example : 1990-02-14
     pub latitude: f32,
     pub longitude: f32,
     pub gender: Gender,
@@ -58,9 +76,167 @@ pub struct DeleteUserRequest {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(try_from = "SwipeUserBody")]
 pub struct SwipeUserRequest {
     pub swiped_uuid: String,
-    pub love: bool, // boolean for sqlite, 0 = dont love, 1 - love
+    pub kind: SwipeKind,
+}
+
+// Clients from before super likes send love instead of kind, true for a like and false for a pass
+#[derive(Deserialize)]
+struct SwipeUserBody {
+    swiped_uuid: String,
+    kind: Option<SwipeKind>,
+    love: Option<bool>,
+}
+
+impl TryFrom<SwipeUserBody> for SwipeUserRequest {
+    type Error = &'static str;
+
+    fn try_from(body: SwipeUserBody) -> Result<SwipeUserRequest, Self::Error> {
+        let kind = match (body.kind, body.love) {
+            (Some(kind), _) => kind,
+            (None, Some(true)) => SwipeKind::Like,
+            (None, Some(false)) => SwipeKind::Pass,
+            (None, None) => return Err("missing field `kind`"),
+        };
+        Ok(SwipeUserRequest {
+            swiped_uuid: body.swiped_uuid,
+            kind,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SwipeKind {
+    #[serde(rename = "pass")]
+    Pass,
+    #[serde(rename = "like")]
+    Like,
+    #[serde(rename = "super_like")]
+    SuperLike,
+}
+
+// Stored in MatchingResults.love, 0 = dont love, 1 = love, 2 = super love
+impl ToSql for SwipeKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            SwipeKind::Pass => Ok(0.into()),
+            SwipeKind::Like => Ok(1.into()),
+            SwipeKind::SuperLike => Ok(2.into()),
+        }
+    }
+}
+
+// PROFILE //////////////////////////////////////
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateProfileRequest {
+    pub interests: Vec<String>, // from PROFILE_INTERESTS
+    pub languages: Vec<String>, // from PROFILE_LANGUAGES
+    pub height: Option<u16>,    // cm
+    pub job: Option<String>,
+    pub education: Option<String>,
+    pub prompts: Vec<ProfilePrompt>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProfilePrompt {
+    pub question: String, // from PROFILE_PROMPTS
+    pub answer: String,
+}
+
+// TRAVEL //////////////////////////////////////
+#[derive(Deserialize)]
+pub struct TravelRequest {
+    pub latitude: f32,
+    pub longitude: f32,
+    pub label: String,  // shown to other users, example : Lisbon
+    pub expiry: String, // ISO8601, the user is back home after it
+}
+
+// DISCOVERY //////////////////////////////////////
+// Every preference is optional, and either excludes the potential lovers not matching it
+// or only ranks them down
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct DiscoveryPreferences {
+    pub has_photos: Option<FilterStrength>,
+    pub verified: Option<FilterStrength>,
+    pub last_active_within_days: Option<Preference<u16>>,
+    pub height: Option<Preference<HeightRange>>, // users without a height don't match
+    pub languages: Option<Preference<Vec<String>>>, // users speaking any of them
+    pub interests: Option<Preference<Vec<String>>>, // users sharing any of them
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Preference<T> {
+    pub value: T,
+    pub strength: FilterStrength,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum FilterStrength {
+    #[serde(rename = "hard")]
+    Hard, // potential lovers not matching are excluded
+    #[serde(rename = "soft")]
+    Soft, // potential lovers not matching are ranked down
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct HeightRange {
+    pub min: u16, // cm
+    pub max: u16,
+}
+
+impl DiscoveryPreferences {
+    // Only the preferences of the given strength
+    pub fn only(&self, strength: FilterStrength) -> DiscoveryPreferences {
+        DiscoveryPreferences {
+            has_photos: self.has_photos.filter(|s| *s == strength),
+            verified: self.verified.filter(|s| *s == strength),
+            last_active_within_days: only(&self.last_active_within_days, strength),
+            height: only(&self.height, strength),
+            languages: only(&self.languages, strength),
+            interests: only(&self.interests, strength),
+        }
+    }
+}
+
+fn only<T: Clone>(
+    preference: &Option<Preference<T>>,
+    strength: FilterStrength,
+) -> Option<Preference<T>> {
+    preference
+        .clone()
+        .filter(|preference| preference.strength == strength)
+}
+
+#[derive(Deserialize)]
+pub struct DeckRequest {
+    pub limit: Option<usize>,
+    // Hard filters for this deck only, replacing the matching discovery preferences
+    pub interests: Option<String>, // comma separated, users sharing any of them
+    pub languages: Option<String>, // comma separated, users speaking any of them
+    pub height_min: Option<u16>,
+    pub height_max: Option<u16>,
+}
+
+// Query strings can't be flattened with numbers in them, the requests taking the deck filters copy them
+pub struct DeckFilters {
+    pub interests: Option<String>,
+    pub languages: Option<String>,
+    pub height_min: Option<u16>,
+    pub height_max: Option<u16>,
+}
+
+impl DeckRequest {
+    pub fn filters(&self) -> DeckFilters {
+        DeckFilters {
+            interests: self.interests.clone(),
+            languages: self.languages.clone(),
+            height_min: self.height_min,
+            height_max: self.height_max,
+        }
+    }
 }
 
 // MESSAGES //////////////////////////////////////
@@ -87,14 +263,29 @@ pub struct TraceRequest {
 }
 
 // STATISTICS //////////////////////////////////////
+// The count is always around the stored location of the user, within their search radius : a location
+// taken from the query would let anyone trilaterate the users counted
 #[derive(Deserialize)]
 pub struct MatchingPotentialRequest {
     pub looking_for: Gender,
-    pub search_radius: u16,
-    pub latitude: f32,
-    pub longitude: f32,
     pub looking_for_age_min: u8,
     pub looking_for_age_max: u8,
+    // The hard filters of DeckRequest
+    pub interests: Option<String>,
+    pub languages: Option<String>,
+    pub height_min: Option<u16>,
+    pub height_max: Option<u16>,
+}
+
+impl MatchingPotentialRequest {
+    pub fn filters(&self) -> DeckFilters {
+        DeckFilters {
+            interests: self.interests.clone(),
+            languages: self.languages.clone(),
+            height_min: self.height_min,
+            height_max: self.height_max,
+        }
+    }
 }
 
 // FEEDBACKS //////////////////////////////////////
@@ -103,9 +294,75 @@ pub struct CreateFeedbackRequest {
     pub feedback_message: String,
 }
 
+// LIKES //////////////////////////////////////
+#[derive(Deserialize)]
+pub struct LikesReceivedRequest {
+    pub page: Option<usize>,
+    pub page_size: Option<usize>,
+}
+
 // PHOTOS //////////////////////////////////////
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SwitchPhotosRequest {
     pub photo_uuid1: String,
     pub photo_uuid2: String,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReorderPhotosRequest {
+    pub photo_uuids: Vec<String>, // every photo of the user, from first to last
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum PhotoStatus {
+    #[serde(rename = "pending")]
+    Pending, // waiting for a review, only shown to its owner
+    #[serde(rename = "approved")]
+    Approved,
+    #[serde(rename = "rejected")]
+    Rejected, // only shown to its owner
+}
+
+impl FromStr for PhotoStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<PhotoStatus, Self::Err> {
+        match input {
+            "pending" => Ok(PhotoStatus::Pending),
+            "approved" => Ok(PhotoStatus::Approved),
+            "rejected" => Ok(PhotoStatus::Rejected),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql for PhotoStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            PhotoStatus::Pending => Ok("pending".into()),
+            PhotoStatus::Approved => Ok("approved".into()),
+            PhotoStatus::Rejected => Ok("rejected".into()),
+        }
+    }
+}
+
+impl FromSql for PhotoStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|_| FromSqlError::InvalidType)
+    }
+}
+
+// MODERATION //////////////////////////////////////
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PendingPhotosRequest {
+    pub page: Option<usize>,
+    pub page_size: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReviewPhotoRequest {
+    pub status: PhotoStatus, // approved or rejected
+}
@@ -1,3 +1,5 @@
+use crate::data_access_layer::lover_dal::ReceivedLike;
+use crate::data_access_layer::user_dal::PotentialLover;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -8,7 +10,37 @@ pub enum SwipeUserResponse {
     NotMatched,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UndoSwipeResponse {
+    pub restored_uuid: String,
+    pub undos_left: usize,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MessageResponse {
     pub message: String,
 }
+
+#[derive(Serialize, Debug)]
+pub struct LikesReceivedResponse {
+    pub total: usize,
+    pub likes: Vec<ReceivedLike>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SwipeQuota {
+    pub daily_quota: usize,
+    pub remaining: usize,
+    pub reset_at: Option<String>, // when a swipe is given back, None if the user didn't swipe in the last 24 hours
+}
+
+// Clients of api version 1 get the bare potential lover, see ApiVersion::bare_potential_lover
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum FindLoverResponse {
+    PotentialLover(PotentialLover),
+    WithSwipeQuota {
+        potential_lover: PotentialLover,
+        swipe_quota: SwipeQuota,
+    },
+}
@@ -81,8 +81,8 @@ pub async fn login(
                             + TOKEN_LIFESPAN,
                     };
                     let my_refresh_claims = JwtClaims {
-                        user_uuid: user_uuid,
-                        private_user_uuid: private_user_uuid,
+                        user_uuid,
+                        private_user_uuid,
                         exp: SystemTime::now()
                             .duration_since(UNIX_EPOCH)
                             .expect("failed getting current timestamp")
@@ -103,8 +103,8 @@ pub async fn login(
                     .map_err(|_| AuthError::TokenCreation)?;
                     response_ok_auth_with_message(
                         Some(LoginResponse {
-                            token: token,
-                            refresh_token: refresh_token,
+                            token,
+                            refresh_token,
                         }),
                         "Successfull login".to_string(),
                     )
@@ -150,7 +150,7 @@ pub async fn token_refresh(
                 &EncodingKey::from_secret(state.key_jwt.as_bytes()),
             )
             .map_err(|_| AuthError::TokenCreation)?;
-            response_auth_ok(Some(RefreshResponse { token: token }))
+            response_auth_ok(Some(RefreshResponse { token }))
         }
         Err(e) => match *e.kind() {
             ErrorKind::InvalidToken => {
@@ -0,0 +1,442 @@
+use crate::configs::app_state::AppState;
+use crate::constants::constants::{
+    DECK_CARD_TTL, DECK_DEFAULT_SIZE, DECK_MAX_SIZE, HEIGHT_MAX, HEIGHT_MIN, LAST_ACTIVE_MAX_DAYS,
+    PROFILE_INTERESTS, PROFILE_LANGUAGES,
+};
+use crate::data_access_layer;
+use crate::data_access_layer::photo_dal::LegacyPhotoFields;
+use crate::data_access_layer::user_dal::{DiscoveryCriteria, PotentialLover};
+use crate::my_errors::service_errors::ServiceError;
+use crate::my_errors::sqlite_errors::SqliteError;
+use crate::requests::requests;
+use crate::requests::requests::{DiscoveryPreferences, FilterStrength, HeightRange, Preference};
+use crate::service_layer::auth_service::JwtClaims;
+use crate::service_layer::ranking_service;
+use crate::service_layer::ranking_service::RankingContext;
+use crate::utilities::api_version::ApiVersion;
+use crate::utilities::responses::{response_ok, response_ok_with_message, ApiResponse};
+use crate::utilities::validation::check_choices;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Cards already served to a user, so that the next deck pages forward instead of
+// serving the same users again. Cards expire after DECK_CARD_TTL if they are not swiped.
+// Restored cards come from undone swipes and are served again first.
+// Expired cards are remembered until they are served again, a late swipe on them is not off deck.
+// A deck unused for DECK_CARD_TTL is dropped, see expire_decks
+pub struct ServedDeck {
+    cards: HashMap<String, Instant>,
+    restored: Vec<String>,
+    expired: HashSet<String>,
+    used_at: Instant,
+}
+
+impl Default for ServedDeck {
+    fn default() -> ServedDeck {
+        ServedDeck {
+            cards: HashMap::new(),
+            restored: Vec::new(),
+            expired: HashSet::new(),
+            used_at: Instant::now(),
+        }
+    }
+}
+
+impl ServedDeck {
+    fn remove_expired_cards(&mut self) {
+        let ttl = Duration::from_secs(DECK_CARD_TTL);
+        let expired = &mut self.expired;
+        self.cards.retain(|card_uuid, served_at| {
+            let fresh = served_at.elapsed() < ttl;
+            if !fresh {
+                expired.insert(card_uuid.clone());
+            }
+            fresh
+        });
+    }
+}
+
+// The deck of the user, created if needed
+fn user_deck<'a>(
+    decks: &'a mut HashMap<String, ServedDeck>,
+    user_uuid: &str,
+) -> &'a mut ServedDeck {
+    let deck = decks.entry(user_uuid.to_string()).or_default();
+    deck.used_at = Instant::now();
+    deck
+}
+
+// Runs for the whole server lifetime, drops the decks of the users who stopped using them
+pub async fn expire_decks(state: Arc<AppState>) {
+    let ttl = Duration::from_secs(DECK_CARD_TTL);
+    let mut interval = tokio::time::interval(ttl);
+    loop {
+        interval.tick().await;
+        let mut decks = state.decks.lock().unwrap();
+        decks.retain(|_, deck| deck.used_at.elapsed() < ttl);
+    }
+}
+
+pub async fn get_deck(
+    jwt_claims: JwtClaims,
+    api_version: ApiVersion,
+    State(state): State<Arc<AppState>>,
+    deck_request: Query<requests::DeckRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<Vec<PotentialLover>>>), ServiceError> {
+    let limit = deck_request
+        .limit
+        .unwrap_or(DECK_DEFAULT_SIZE)
+        .clamp(1, DECK_MAX_SIZE);
+    let user = data_access_layer::user_dal::get_user_by_uuid(&state, jwt_claims.user_uuid.clone())?;
+    let preferences = data_access_layer::preference_dal::get_discovery_preferences(
+        &state,
+        jwt_claims.user_uuid.clone(),
+    )?;
+    let preferences = with_deck_filters(preferences, &deck_request.filters())?;
+    let criteria = DiscoveryCriteria::from_user(&user, &preferences)?;
+
+    let mut cards = serve_cards(&state, &criteria, limit)?;
+    for card in cards.iter_mut() {
+        card.legacy_photos = LegacyPhotoFields::for_version(api_version, &card.photos);
+    }
+
+    response_ok(Some(cards))
+}
+
+pub async fn get_preferences(
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+) -> Result<(StatusCode, Json<ApiResponse<DiscoveryPreferences>>), ServiceError> {
+    let preferences =
+        data_access_layer::preference_dal::get_discovery_preferences(&state, jwt_claims.user_uuid)?;
+    response_ok(Some(preferences))
+}
+
+pub async fn update_preferences(
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+    Json(preferences): Json<DiscoveryPreferences>,
+) -> Result<(StatusCode, Json<ApiResponse<()>>), ServiceError> {
+    if let Some(days) = &preferences.last_active_within_days {
+        if days.value == 0 || days.value > LAST_ACTIVE_MAX_DAYS {
+            return Err(ServiceError::ValueNotAccepted(
+                days.value.to_string(),
+                format!("last active within should be 1 to {LAST_ACTIVE_MAX_DAYS} days"),
+            ));
+        }
+    }
+    if let Some(height) = &preferences.height {
+        if height.value.min < HEIGHT_MIN
+            || height.value.max > HEIGHT_MAX
+            || height.value.min > height.value.max
+        {
+            return Err(ServiceError::ValueNotAccepted(
+                format!("{}-{}", height.value.min, height.value.max),
+                format!("height range should be within {HEIGHT_MIN} and {HEIGHT_MAX} cm"),
+            ));
+        }
+    }
+    // An empty list would match nobody, the preference should be removed instead
+    if let Some(languages) = &preferences.languages {
+        if languages.value.is_empty() {
+            return Err(ServiceError::ValueNotAccepted(
+                "[]".to_string(),
+                "languages should not be empty".to_string(),
+            ));
+        }
+        check_choices(
+            &languages.value,
+            PROFILE_LANGUAGES,
+            PROFILE_LANGUAGES.len(),
+            "languages",
+        )?;
+    }
+    if let Some(interests) = &preferences.interests {
+        if interests.value.is_empty() {
+            return Err(ServiceError::ValueNotAccepted(
+                "[]".to_string(),
+                "interests should not be empty".to_string(),
+            ));
+        }
+        check_choices(
+            &interests.value,
+            PROFILE_INTERESTS,
+            PROFILE_INTERESTS.len(),
+            "interests",
+        )?;
+    }
+
+    data_access_layer::preference_dal::set_discovery_preferences(
+        &state,
+        jwt_claims.user_uuid,
+        &preferences,
+    )?;
+    response_ok_with_message(None::<()>, "discovery preferences updated".to_string())
+}
+
+// The stored preferences, with the hard filters of a deck request replacing the matching ones.
+// Also used by statistics_service::matching_potential, so that it counts what the deck would show
+pub fn with_deck_filters(
+    mut preferences: DiscoveryPreferences,
+    filters: &requests::DeckFilters,
+) -> Result<DiscoveryPreferences, ServiceError> {
+    let interests = split_list(filters.interests.as_deref());
+    check_choices(
+        &interests,
+        PROFILE_INTERESTS,
+        PROFILE_INTERESTS.len(),
+        "interests",
+    )?;
+    if !interests.is_empty() {
+        preferences.interests = Some(Preference {
+            value: interests,
+            strength: FilterStrength::Hard,
+        });
+    }
+    let languages = split_list(filters.languages.as_deref());
+    check_choices(
+        &languages,
+        PROFILE_LANGUAGES,
+        PROFILE_LANGUAGES.len(),
+        "languages",
+    )?;
+    if !languages.is_empty() {
+        preferences.languages = Some(Preference {
+            value: languages,
+            strength: FilterStrength::Hard,
+        });
+    }
+    if filters.height_min.is_some() || filters.height_max.is_some() {
+        preferences.height = Some(Preference {
+            value: HeightRange {
+                min: filters.height_min.unwrap_or(HEIGHT_MIN),
+                max: filters.height_max.unwrap_or(HEIGHT_MAX),
+            },
+            strength: FilterStrength::Hard,
+        });
+    }
+    Ok(preferences)
+}
+
+// "a,b" -> ["a", "b"]
+fn split_list(list: Option<&str>) -> Vec<String> {
+    list.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Get the `limit` best ranked potential lovers that were not served recently and remember them as served
+pub fn serve_cards(
+    state: &Arc<AppState>,
+    criteria: &DiscoveryCriteria,
+    limit: usize,
+) -> Result<Vec<PotentialLover>, SqliteError> {
+    // The lock is shared by every user, it is not kept while querying
+    let (served_count, restored) = {
+        let mut decks = state.decks.lock().unwrap();
+        let deck = user_deck(&mut decks, &criteria.user_uuid);
+        deck.remove_expired_cards();
+        (deck.cards.len(), deck.restored.clone())
+    };
+
+    let pool_size = state.candidate_pool.max(limit + served_count);
+    let candidates = data_access_layer::user_dal::find_love_targets(state, criteria, pool_size)?;
+    let context = RankingContext {
+        now: Utc::now(),
+        search_radius: criteria.search_radius,
+        age: criteria.age,
+        interests: criteria.interests.clone(),
+        soft_preferences: criteria.soft_preferences.clone(),
+    };
+    let mut ranked = state.ranker.rank(candidates, &context);
+    ranked.sort_by_key(|candidate| !restored.contains(&candidate.lover.uuid));
+
+    // Cards served by a concurrent call meanwhile are filtered out here, they can't be served twice
+    let mut decks = state.decks.lock().unwrap();
+    let deck = user_deck(&mut decks, &criteria.user_uuid);
+    let now = Instant::now();
+    let cards = ranked
+        .into_iter()
+        .filter(|candidate| !deck.cards.contains_key(&candidate.lover.uuid))
+        .take(limit)
+        .map(|candidate| PotentialLover {
+            compatibility: ranking_service::compatibility(&candidate, &context),
+            ..candidate.lover
+        })
+        .collect::<Vec<PotentialLover>>();
+    for card in cards.iter() {
+        deck.expired.remove(&card.uuid);
+        deck.cards.insert(card.uuid.clone(), now);
+    }
+    deck.restored
+        .retain(|restored_uuid| !deck.cards.contains_key(restored_uuid));
+
+    Ok(cards)
+}
+
+// Remove the swiped card from the user deck, return false if the card was never served to the user.
+// Decks only live in memory : without a deck, after a restart or once it was dropped, nothing is known
+pub fn consume_card(state: &Arc<AppState>, user_uuid: &str, swiped_uuid: &str) -> bool {
+    let mut decks = state.decks.lock().unwrap();
+    if !decks.contains_key(user_uuid) {
+        return true;
+    }
+    let deck = user_deck(&mut decks, user_uuid);
+    deck.cards.remove(swiped_uuid).is_some() || deck.expired.remove(swiped_uuid)
+}
+
+// Put back a card whose swipe was undone at the top of the user deck
+pub fn restore_card(state: &Arc<AppState>, user_uuid: &str, swiped_uuid: String) {
+    let mut decks = state.decks.lock().unwrap();
+    let deck = user_deck(&mut decks, user_uuid);
+    deck.cards.remove(&swiped_uuid);
+    deck.restored.push(swiped_uuid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access_layer::user_dal::get_user_by_uuid;
+    use crate::utilities::test_fixtures::{app_state, insert_user, paris_east, UserFixture};
+
+    #[tokio::test]
+    async fn only_cards_never_served_are_off_deck() {
+        let state = app_state().await;
+        // Without a deck nothing is known, every swipe is accepted
+        assert!(consume_card(&state, "ann", "bob"));
+
+        {
+            let mut decks = state.decks.lock().unwrap();
+            let deck = user_deck(&mut decks, "ann");
+            let expired_at = Instant::now() - Duration::from_secs(DECK_CARD_TTL + 1);
+            deck.cards.insert("bob".to_string(), Instant::now());
+            deck.cards.insert("carl".to_string(), expired_at);
+            deck.remove_expired_cards();
+        }
+        assert!(consume_card(&state, "ann", "bob"));
+        assert!(consume_card(&state, "ann", "carl"));
+        assert!(!consume_card(&state, "ann", "carl"));
+        assert!(!consume_card(&state, "ann", "dan"));
+    }
+
+    #[tokio::test]
+    async fn served_cards_carry_their_compatibility() {
+        let state = app_state().await;
+        let ann = insert_user(&state, UserFixture::default());
+        let man = |name, km| UserFixture {
+            name,
+            longitude: paris_east(km),
+            gender: "male",
+            looking_for: "female",
+            ..Default::default()
+        };
+        let bob = insert_user(&state, man("Bob", 9.8));
+        insert_user(&state, man("Carl", 10.2));
+        let set_interests = |user_uuid: &str, interests: &str| {
+            state
+                .connection
+                .get()
+                .unwrap()
+                .execute(
+                    "UPDATE Users SET interests = ? WHERE user_uuid = ?",
+                    [interests, user_uuid],
+                )
+                .unwrap();
+        };
+        set_interests(&ann, r#"["hiking","board_games","cinema"]"#);
+        set_interests(&bob, r#"["board_games","cinema"]"#);
+
+        let user = get_user_by_uuid(&state, ann).unwrap();
+        let criteria =
+            DiscoveryCriteria::from_user(&user, &DiscoveryPreferences::default()).unwrap();
+        let cards = serve_cards(&state, &criteria, 10).unwrap();
+        let card = |name| cards.iter().find(|card| card.name == name).unwrap();
+
+        let (bob, carl) = (card("Bob"), card("Carl"));
+        assert_eq!(
+            bob.compatibility.shared_interests,
+            vec!["board_games", "cinema"]
+        );
+        assert_eq!(bob.compatibility.interests, 1.);
+        assert!(carl.compatibility.shared_interests.is_empty());
+        // Both are shown 10 km away, their distance scores can't tell them apart
+        assert_eq!(
+            (bob.distance.as_str(), carl.distance.as_str()),
+            ("10 km", "10 km")
+        );
+        assert_eq!(bob.compatibility.distance, carl.compatibility.distance);
+        assert!(bob.compatibility.score > carl.compatibility.score);
+    }
+
+    #[tokio::test]
+    async fn deck_filters_apply_to_the_deck_and_the_count() {
+        let state = app_state().await;
+        let ann = insert_user(&state, UserFixture::default());
+        for (name, height, languages) in [
+            ("Bob", 185, r#"["fr"]"#),
+            ("Carl", 170, r#"["fr"]"#),
+            ("Dan", 190, r#"["de"]"#),
+        ] {
+            let user_uuid = insert_user(
+                &state,
+                UserFixture {
+                    name,
+                    gender: "male",
+                    looking_for: "female",
+                    ..Default::default()
+                },
+            );
+            state
+                .connection
+                .get()
+                .unwrap()
+                .execute(
+                    "UPDATE Users SET height = ?, languages = ? WHERE user_uuid = ?",
+                    rusqlite::params![height, languages, user_uuid],
+                )
+                .unwrap();
+        }
+
+        let filters = requests::DeckFilters {
+            interests: None,
+            languages: Some("fr, it".to_string()),
+            height_min: Some(180),
+            height_max: None,
+        };
+        let preferences = with_deck_filters(DiscoveryPreferences::default(), &filters).unwrap();
+        let user = get_user_by_uuid(&state, ann).unwrap();
+        let criteria = DiscoveryCriteria::from_user(&user, &preferences).unwrap();
+
+        let cards = serve_cards(&state, &criteria, 10).unwrap();
+        assert_eq!(
+            cards
+                .iter()
+                .map(|card| card.name.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["Bob"]
+        );
+        assert_eq!(
+            data_access_layer::lover_dal::potential_matches_count(&state, &criteria).unwrap(),
+            1
+        );
+
+        let filters = requests::DeckFilters {
+            languages: Some("french".to_string()),
+            ..filters
+        };
+        assert!(matches!(
+            with_deck_filters(DiscoveryPreferences::default(), &filters),
+            Err(ServiceError::ValueNotAccepted(..))
+        ));
+    }
+}
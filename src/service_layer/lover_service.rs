@@ -1,11 +1,17 @@
 use crate::configs::app_state::AppState;
+use crate::constants::constants::{LIKES_PAGE_DEFAULT_SIZE, LIKES_PAGE_MAX_SIZE};
 use crate::data_access_layer;
 use crate::data_access_layer::lover_dal::LoveWithLover;
+use crate::data_access_layer::photo_dal::{LegacyFirstPhoto, LegacyPhotoFields};
 use crate::my_errors::service_errors::ServiceError;
+use crate::requests::requests;
+use crate::responses::responses;
 use crate::service_layer::auth_service::JwtClaims;
+use crate::utilities::api_version::ApiVersion;
 use crate::utilities::responses::{response_ok, ApiResponse};
+use crate::utilities::validation::page_offset;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     Json,
 };
@@ -13,6 +19,7 @@ use std::sync::Arc;
 
 pub async fn get_lovers(
     jwt_claims: JwtClaims,
+    api_version: ApiVersion,
     State(state): State<Arc<AppState>>,
     Path(user_uuid): Path<String>,
 ) -> Result<(StatusCode, Json<ApiResponse<Vec<LoveWithLover>>>), ServiceError> {
@@ -21,7 +28,13 @@ pub async fn get_lovers(
     }
     let lovers_found = data_access_layer::lover_dal::get_lovers(&state, user_uuid);
     match lovers_found {
-        Ok(lovers) => response_ok(Some(lovers)),
+        Ok(mut lovers) => {
+            for lover in lovers.iter_mut() {
+                lover.legacy_first_photo =
+                    LegacyFirstPhoto::for_version(api_version, lover.first_photo.as_ref());
+            }
+            response_ok(Some(lovers))
+        }
         Err(err) => Err(ServiceError::Sqlite(err)),
     }
 }
@@ -36,3 +49,54 @@ pub async fn tick_love(
     data_access_layer::lover_dal::tick_love(&state, love_uuid, jwt_claims.user_uuid)?;
     response_ok(None::<()>)
 }
+
+// People who love the user and that the user has not swiped yet. Only the first
+// likes_received_visible likes are shown in clear, the next ones are blurred or not sent.
+pub async fn get_likes_received(
+    jwt_claims: JwtClaims,
+    api_version: ApiVersion,
+    State(state): State<Arc<AppState>>,
+    likes_request: Query<requests::LikesReceivedRequest>,
+) -> Result<
+    (
+        StatusCode,
+        Json<ApiResponse<responses::LikesReceivedResponse>>,
+    ),
+    ServiceError,
+> {
+    let page_size = likes_request
+        .page_size
+        .unwrap_or(LIKES_PAGE_DEFAULT_SIZE)
+        .clamp(1, LIKES_PAGE_MAX_SIZE);
+    let page = likes_request.page.unwrap_or(0);
+    let offset = page_offset(page, page_size)?;
+    let limit = if state.likes_received_blur {
+        page_size
+    } else {
+        page_size.min(state.likes_received_visible.saturating_sub(offset))
+    };
+
+    let total =
+        data_access_layer::lover_dal::likes_received_count(&state, jwt_claims.user_uuid.clone())?;
+    let mut likes = if limit > 0 {
+        data_access_layer::lover_dal::get_likes_received(
+            &state,
+            jwt_claims.user_uuid,
+            limit,
+            offset,
+        )?
+    } else {
+        Vec::new()
+    };
+    for (index, like) in likes.iter_mut().enumerate() {
+        if offset + index >= state.likes_received_visible {
+            like.uuid = "".to_string();
+            like.name = "".to_string();
+            like.photos = Vec::new();
+            like.blurred = true;
+        }
+        like.legacy_photos = LegacyPhotoFields::for_version(api_version, &like.photos);
+    }
+
+    response_ok(Some(responses::LikesReceivedResponse { total, likes }))
+}
@@ -62,7 +62,7 @@ pub async fn create_message(
             uuid_message: uuid_message.clone(),
             message: create_message_request.message.to_string(),
             poster_uuid: create_message_request.poster_uuid,
-            creation_datetime: creation_datetime,
+            creation_datetime,
         },
     };
 
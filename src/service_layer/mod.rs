@@ -1,9 +1,14 @@
 pub mod auth_service;
+pub mod discovery_service;
 pub mod feedback_service;
 pub mod lover_service;
 pub mod message_service;
+pub mod moderation_service;
 pub mod photos_service;
+pub mod ranking_service;
 pub mod sse_service;
 pub mod statistics_service;
+pub mod swipe_limits_service;
 pub mod trace_service;
+pub mod travel_service;
 pub mod user_service;
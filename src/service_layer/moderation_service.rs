@@ -0,0 +1,119 @@
+use crate::configs::app_state::AppState;
+use crate::configs::config::{ModerationConfig, ModeratorKind};
+use crate::constants::constants::{
+    PENDING_PHOTOS_PAGE_DEFAULT_SIZE, PENDING_PHOTOS_PAGE_MAX_SIZE, PHOTO_MAX_ASPECT_RATIO,
+    PHOTO_MIN_DIMENSION,
+};
+use crate::data_access_layer::photo_dal;
+use crate::data_access_layer::photo_dal::Photo;
+use crate::my_errors::service_errors::ServiceError;
+use crate::my_errors::sqlite_errors::SqliteError;
+use crate::requests::requests;
+use crate::requests::requests::PhotoStatus;
+use crate::service_layer::auth_service::JwtClaims;
+use crate::utilities::responses::{response_ok, response_ok_with_message, ApiResponse};
+use crate::utilities::validation::page_offset;
+use axum::extract::{Path, Query, State};
+use axum::{http::StatusCode, Json};
+use image::{DynamicImage, GenericImageView};
+use std::sync::Arc;
+
+pub enum ModerationDecision {
+    Approve,
+    Review,         // the photo waits for an admin, see review_photo
+    Reject(String), // Reason, the upload is refused
+}
+
+// Decides what happens to an uploaded photo before it is stored, duplicates are handled by
+// photos_service::save_photo. Runs on a blocking thread, it may be cpu bound
+pub trait PhotoModerator: Send + Sync {
+    fn moderate(&self, image: &DynamicImage) -> ModerationDecision;
+}
+
+// Every photo goes live on upload
+pub struct NoModerator;
+
+impl PhotoModerator for NoModerator {
+    fn moderate(&self, _: &DynamicImage) -> ModerationDecision {
+        ModerationDecision::Approve
+    }
+}
+
+// Refuses the photos that can't be a good profile photo, the others are reviewed by an admin
+pub struct RulesModerator;
+
+impl PhotoModerator for RulesModerator {
+    fn moderate(&self, image: &DynamicImage) -> ModerationDecision {
+        let (width, height) = image.dimensions();
+        if width.min(height) < PHOTO_MIN_DIMENSION {
+            return ModerationDecision::Reject(format!(
+                "the photo should be at least {PHOTO_MIN_DIMENSION} pixels wide and high"
+            ));
+        }
+        if width.max(height) as f32 / width.min(height) as f32 > PHOTO_MAX_ASPECT_RATIO {
+            return ModerationDecision::Reject(format!(
+                "the photo should not be more than {PHOTO_MAX_ASPECT_RATIO} times longer than wide"
+            ));
+        }
+        ModerationDecision::Review
+    }
+}
+
+pub fn from_config(config: &ModerationConfig) -> Box<dyn PhotoModerator> {
+    match config.moderator {
+        ModeratorKind::None => Box::new(NoModerator),
+        ModeratorKind::Rules => Box::new(RulesModerator),
+    }
+}
+
+// Photos waiting for a review, the oldest first
+pub async fn get_pending_photos(
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+    pending_request: Query<requests::PendingPhotosRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<Vec<Photo>>>), ServiceError> {
+    check_admin(&state, &jwt_claims)?;
+    let page_size = pending_request
+        .page_size
+        .unwrap_or(PENDING_PHOTOS_PAGE_DEFAULT_SIZE)
+        .clamp(1, PENDING_PHOTOS_PAGE_MAX_SIZE);
+    let offset = page_offset(pending_request.page.unwrap_or(0), page_size)?;
+    let photos = photo_dal::get_photos_by_status(&state, PhotoStatus::Pending, page_size, offset)?;
+    response_ok(Some(photos))
+}
+
+pub async fn review_photo(
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+    Path(photo_uuid): Path<String>,
+    Json(review_request): Json<requests::ReviewPhotoRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<()>>), ServiceError> {
+    check_admin(&state, &jwt_claims)?;
+    if review_request.status == PhotoStatus::Pending {
+        return Err(ServiceError::ValueNotAccepted(
+            "pending".to_string(),
+            "a review either approves or rejects the photo".to_string(),
+        ));
+    }
+
+    match photo_dal::set_photo_status(&state, photo_uuid.clone(), review_request.status) {
+        Ok(_) => response_ok_with_message(None::<()>, "photo reviewed".to_string()),
+        Err(SqliteError::NotFound) => Err(ServiceError::ValueNotAccepted(
+            photo_uuid,
+            "no photo with this uuid".to_string(),
+        )),
+        Err(err) => Err(ServiceError::Sqlite(err)),
+    }
+}
+
+fn check_admin(state: &AppState, jwt_claims: &JwtClaims) -> Result<(), ServiceError> {
+    if state
+        .moderation_config
+        .admins
+        .contains(&jwt_claims.user_uuid)
+    {
+        Ok(())
+    } else {
+        Err(ServiceError::ForbiddenQuery)
+    }
+}
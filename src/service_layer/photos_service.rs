@@ -1,52 +1,32 @@
 use crate::configs::app_state::AppState;
-use crate::data_access_layer;
+use crate::constants::constants::{
+    PHOTOS_MAX, PHOTO_DELETION_BATCH_SIZE, PHOTO_DELETION_MAX_RETRY_DELAY,
+    PHOTO_DELETION_RETRY_DELAY, PHOTO_DELETION_SWEEP_INTERVAL, PHOTO_DUPLICATE_DISTANCE,
+    PHOTO_RECONCILIATION_GRACE,
+};
+use crate::data_access_layer::flag_dal::FlagReason;
 use crate::data_access_layer::photo_dal;
+use crate::data_access_layer::photo_dal::{NewPhoto, Photo, PhotoHash, PhotoVariants};
 use crate::my_errors::service_errors::ServiceError;
+use crate::my_errors::sqlite_errors::SqliteError;
+use crate::my_errors::store_errors::StoreError;
 use crate::requests::requests;
+use crate::requests::requests::PhotoStatus;
 use crate::service_layer::auth_service::JwtClaims;
-use crate::utilities::responses::{response_ok, ApiResponse};
+use crate::service_layer::moderation_service::ModerationDecision;
+use crate::service_layer::swipe_limits_service::flag;
+use crate::utilities::images;
+use crate::utilities::images::{PhotoType, PhotoVariant};
+use crate::utilities::responses::{response_ok, response_ok_with_message, ApiResponse};
 
-use aws_smithy_http;
 use axum::extract::{Multipart, Path, State};
 use axum::{http::StatusCode, Json};
-use std::fmt;
-use std::str::FromStr;
+use chrono::{DateTime, Duration, Utc};
+use image::DynamicImage;
+use std::collections::{BTreeSet, HashSet};
 use std::sync::Arc;
 use uuid::Uuid;
 
-enum PhotoType {
-    Png,
-    Jpg,
-    Jpeg,
-}
-
-impl FromStr for PhotoType {
-    type Err = ServiceError;
-
-    fn from_str(input: &str) -> Result<PhotoType, Self::Err> {
-        match input {
-            // todo : check now that we use canvas image
-            "image/png" => Ok(PhotoType::Png),
-            "image/jpg" => Ok(PhotoType::Jpg),
-            "image/jpeg" => Ok(PhotoType::Jpeg),
-            format => Err(ServiceError::ValueNotAccepted(
-                format.to_string(),
-                "Chose between png, jpg and jpeg.".to_string(),
-            )),
-        }
-    }
-}
-
-impl fmt::Display for PhotoType {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            PhotoType::Png => write!(f, "image/png"),
-            PhotoType::Jpg => write!(f, "image/jpg"),
-            PhotoType::Jpeg => write!(f, "image/jpeg"),
-        }
-    }
-}
-
 pub async fn save_photo(
     jwt_claims: JwtClaims,
     State(state): State<Arc<AppState>>,
@@ -61,46 +41,99 @@ pub async fn save_photo(
         )))?
         .ok_or(ServiceError::Internal)?;
 
-    let user = data_access_layer::user_dal::get_user_by_uuid(&state, jwt_claims.user_uuid.clone())?;
-    let display_order = if let Some(existing_photos) = user.photo_urls {
-        existing_photos.split(',').collect::<Vec<&str>>().len() + 1
-    } else {
-        1
-    };
-
-    if display_order > 6 {
-        return Err(ServiceError::ValueNotAccepted(
-            display_order.to_string(),
-            "You can only have up to 6 photos".to_string(),
-        ));
+    // Checked again when the photo is saved, this only spares decoding a photo that can't be kept
+    let photos_count = photo_dal::get_user_photos(&state, jwt_claims.user_uuid.clone())?.len();
+    if photos_count >= PHOTOS_MAX {
+        return Err(too_many_photos(photos_count + 1));
     }
 
-    let image_key = Uuid::now_v7().to_string();
-    let content_type = PhotoType::from_str(field.content_type().ok_or(ServiceError::Internal)?)?;
-    let image_data: aws_smithy_http::byte_stream::ByteStream =
-        field.bytes().await.or(Err(ServiceError::Internal))?.into();
-
-    if let Err(e) = state
-        .aws_client
-        .upload_object(&image_key, &content_type.to_string(), image_data)
+    let photo_uuid = Uuid::now_v7().to_string();
+    let image_data = field
+        .bytes()
+        .await
+        .or(Err(ServiceError::Internal))?
+        .to_vec();
+    // Held until the variants are rendered, a decoded photo can take PHOTO_MAX_DECODED_SIZE of memory
+    let permit = state
+        .photo_permits
+        .acquire()
         .await
+        .map_err(|_| ServiceError::Internal)?;
+    let moderation_state = state.clone();
+    let (image, perceptual_hash, decision) = tokio::task::spawn_blocking(move || {
+        let image = images::decode_photo(&image_data)?;
+        let perceptual_hash = images::perceptual_hash(&image);
+        let decision = moderation_state.photo_moderator.moderate(&image);
+        Ok::<_, ServiceError>((image, perceptual_hash, decision))
+    })
+    .await
+    .map_err(|_| ServiceError::Internal)??;
+
+    let duplicates = find_duplicates(&state, perceptual_hash)?;
+    if duplicates
+        .iter()
+        .any(|duplicate| duplicate.user_uuid == jwt_claims.user_uuid)
     {
-        println!("aws upload object error: {:?}", e);
-        return Err(ServiceError::Internal);
+        return Err(ServiceError::ImageRejected(
+            "the photo is already uploaded".to_string(),
+        ));
+    }
+    let mut status = match decision {
+        ModerationDecision::Approve => PhotoStatus::Approved,
+        ModerationDecision::Review => PhotoStatus::Pending,
+        ModerationDecision::Reject(reason) => return Err(ServiceError::ImageRejected(reason)),
+    };
+    // A photo of another user may be a stolen one, it is reviewed whatever the moderator decided
+    let duplicate_of = duplicates
+        .first()
+        .map(|duplicate| duplicate.photo_uuid.clone());
+    if duplicate_of.is_some() {
+        flag(&state, &jwt_claims.user_uuid, FlagReason::DuplicatePhoto);
+        status = PhotoStatus::Pending;
     }
 
-    let url = format!("{}{}", state.aws_client.r2_image_domain, image_key);
-    data_access_layer::photo_dal::create_user_photo(
-        &state,
-        image_key,
-        jwt_claims.user_uuid,
-        url,
-        display_order,
-    )?;
+    let processed = process_photo(&state, &photo_uuid, image).await;
+    drop(permit);
+    let created = processed.and_then(|(variants, blurhash)| {
+        photo_dal::create_user_photo(
+            &state,
+            NewPhoto {
+                photo_uuid: photo_uuid.clone(),
+                user_uuid: jwt_claims.user_uuid,
+                variants,
+                blurhash,
+                perceptual_hash,
+                duplicate_of,
+                status,
+            },
+        )
+        .map_err(|err| match err {
+            // Another upload of the user took the last place meanwhile
+            SqliteError::Conflict => too_many_photos(PHOTOS_MAX + 1),
+            err => ServiceError::Sqlite(err),
+        })
+    });
+    if let Err(e) = created {
+        // Variants may be stored already, they would be left without photo
+        if let Err(queue_error) = photo_dal::queue_photo_deletions(&state, &[photo_uuid]) {
+            println!("failed to queue the unsaved photo : {:?}", queue_error);
+        }
+        return Err(e);
+    }
 
+    if status == PhotoStatus::Pending {
+        return response_ok_with_message(None::<()>, "photo waiting for a review".to_string());
+    }
     response_ok(None::<()>)
 }
 
+fn too_many_photos(photos_count: usize) -> ServiceError {
+    ServiceError::ValueNotAccepted(
+        photos_count.to_string(),
+        format!("You can only have up to {} photos", PHOTOS_MAX),
+    )
+}
+
 pub async fn delete_photo(
     jwt_claims: JwtClaims,
     State(state): State<Arc<AppState>>,
@@ -108,31 +141,306 @@ pub async fn delete_photo(
 ) -> Result<(StatusCode, Json<ApiResponse<()>>), ServiceError> {
     let user_photos = photo_dal::get_user_photos(&state, jwt_claims.user_uuid.clone())?;
 
-    let mut photo_owned_by_user = false;
-    let mut order_shift = 0;
-    for photo in user_photos {
-        if photo.photo_uuid == photo_uuid {
-            photo_owned_by_user = true;
-            order_shift = photo.display_order;
-            break;
+    let photo_owned_by_user = user_photos
+        .iter()
+        .any(|photo| photo.photo_uuid == photo_uuid);
+    if !photo_owned_by_user {
+        return Err(ServiceError::ForbiddenQuery);
+    }
+    photo_dal::delete_photo(&state, jwt_claims.user_uuid, photo_uuid)?;
+    response_ok(None::<()>)
+}
+
+// Photos near identical to the one with this perceptual hash, among the photos of every user,
+// the closest first. Flat photos are never duplicates, see images::is_low_entropy
+fn find_duplicates(
+    state: &Arc<AppState>,
+    perceptual_hash: u64,
+) -> Result<Vec<PhotoHash>, ServiceError> {
+    if images::is_low_entropy(perceptual_hash) {
+        return Ok(Vec::new());
+    }
+    let mut duplicates = photo_dal::get_duplicate_candidates(state, perceptual_hash)?
+        .into_iter()
+        .filter(|photo| {
+            !images::is_low_entropy(photo.perceptual_hash)
+                && images::hash_distance(photo.perceptual_hash, perceptual_hash)
+                    <= PHOTO_DUPLICATE_DISTANCE
+        })
+        .collect::<Vec<PhotoHash>>();
+    duplicates.sort_by_key(|photo| images::hash_distance(photo.perceptual_hash, perceptual_hash));
+    Ok(duplicates)
+}
+
+// Render the variants of an uploaded photo and store them, returning their urls and the photo blurhash.
+// Only the pixels are kept, metadata such as the EXIF GPS coordinates is never stored
+async fn process_photo(
+    state: &AppState,
+    photo_uuid: &str,
+    image: DynamicImage,
+) -> Result<(PhotoVariants, String), ServiceError> {
+    // Decoding and resizing are cpu bound, they would stall the other requests of this worker
+    let (variants, blurhash) = tokio::task::spawn_blocking(move || {
+        Ok::<_, ServiceError>((images::render_variants(&image)?, images::blurhash(&image)?))
+    })
+    .await
+    .map_err(|_| ServiceError::Internal)??;
+
+    for (variant, data) in variants {
+        state
+            .object_store
+            .put(&variant.key(photo_uuid), &PhotoType::Jpeg.to_string(), data)
+            .await?;
+    }
+
+    let url = |variant: PhotoVariant| state.object_store.public_url(&variant.key(photo_uuid));
+    let variants = PhotoVariants {
+        thumbnail: url(PhotoVariant::Thumbnail),
+        card: url(PhotoVariant::Card),
+        full: url(PhotoVariant::Full),
+    };
+    Ok((variants, blurhash))
+}
+
+// One-off command : photos uploaded before variants existed are stored as uploaded, metadata
+// included. Their original is replaced by the processed full variant and the other variants are added.
+// Photos uploaded before blurhashes or perceptual hashes existed are processed again to get them.
+// An original that can't be decoded would keep being served with its metadata, its photo is only
+// deleted with delete_rejected, otherwise it is reported and left as is
+pub async fn reprocess_photos(state: &Arc<AppState>, delete_rejected: bool) {
+    let photos = match photo_dal::get_unprocessed_photos(state) {
+        Ok(photos) => photos,
+        Err(e) => {
+            println!("failed to get the photos to reprocess : {:?}", e);
+            return;
+        }
+    };
+    println!("{} photos to reprocess", photos.len());
+    let mut failures = 0;
+    let mut rejected = 0;
+    let mut deleted = 0;
+    for photo in photos {
+        match reprocess_photo(state, photo.photo_uuid.clone()).await {
+            Ok(_) => {}
+            Err(ServiceError::ImageRejected(reason)) if !delete_rejected => {
+                println!(
+                    "photo {} of user {} rejected, left as is : {}",
+                    photo.photo_uuid, photo.user_uuid, reason
+                );
+                rejected += 1;
+            }
+            Err(ServiceError::ImageRejected(reason)) => {
+                println!(
+                    "photo {} of user {} rejected, deleting it : {}",
+                    photo.photo_uuid, photo.user_uuid, reason
+                );
+                match photo_dal::delete_photo(state, photo.user_uuid, photo.photo_uuid.clone()) {
+                    Ok(_) => deleted += 1,
+                    Err(e) => {
+                        println!("failed to delete photo {} : {:?}", photo.photo_uuid, e);
+                        failures += 1;
+                    }
+                }
+            }
+            Err(e) => {
+                println!("failed to reprocess photo {} : {:?}", photo.photo_uuid, e);
+                failures += 1;
+            }
         }
     }
-    if photo_owned_by_user {
-        let deletion_result = state.aws_client.delete_object(&photo_uuid).await;
-        match deletion_result {
-            Ok(_) => {
-                photo_dal::delete_photo(&state, photo_uuid)?;
-                photo_dal::shift_order_photos(&state, jwt_claims.user_uuid, order_shift)?;
+    delete_queued_photos(state).await;
+    println!(
+        "photos reprocessed, {} rejected and left as is, {} deleted, {} failures",
+        rejected, deleted, failures
+    );
+}
+
+async fn reprocess_photo(state: &Arc<AppState>, photo_uuid: String) -> Result<(), ServiceError> {
+    let permit = state
+        .photo_permits
+        .acquire()
+        .await
+        .map_err(|_| ServiceError::Internal)?;
+    let original = state.object_store.get(&photo_uuid).await?;
+    let (image, perceptual_hash) = tokio::task::spawn_blocking(move || {
+        let image = images::decode_photo(&original)?;
+        let perceptual_hash = images::perceptual_hash(&image);
+        Ok::<_, ServiceError>((image, perceptual_hash))
+    })
+    .await
+    .map_err(|_| ServiceError::Internal)??;
+    let (variants, blurhash) = process_photo(state, &photo_uuid, image).await?;
+    drop(permit);
+    photo_dal::update_processed_photo(state, photo_uuid, &variants, blurhash, perceptual_hash)?;
+    Ok(())
+}
+
+// Delete every variant of the photo from the object store
+async fn delete_photo_objects(state: &AppState, photo_uuid: &str) -> Result<(), StoreError> {
+    for variant in PhotoVariant::ALL {
+        state.object_store.delete(&variant.key(photo_uuid)).await?;
+    }
+    Ok(())
+}
+
+// Runs for the whole server lifetime. Deleting a photo or a user only queues the deletion of its
+// objects, so that an object store failure can't leave them behind
+pub async fn process_photo_deletions(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        PHOTO_DELETION_SWEEP_INTERVAL,
+    ));
+    loop {
+        interval.tick().await;
+        delete_queued_photos(&state).await;
+    }
+}
+
+// Delete the objects of the queued photos that are due, a failed deletion is retried later with
+// a delay doubling after each attempt
+pub async fn delete_queued_photos(state: &Arc<AppState>) {
+    let deletions = match photo_dal::get_due_photo_deletions(state, PHOTO_DELETION_BATCH_SIZE) {
+        Ok(deletions) => deletions,
+        Err(e) => {
+            println!("failed to get the queued photo deletions : {:?}", e);
+            return;
+        }
+    };
+    for deletion in deletions {
+        let result = match delete_photo_objects(state, &deletion.photo_uuid).await {
+            Ok(_) => photo_dal::complete_photo_deletion(state, deletion.photo_uuid),
+            Err(e) => {
+                println!(
+                    "deleting photo {} failed, attempt {} : {}",
+                    deletion.photo_uuid,
+                    deletion.attempts + 1,
+                    e
+                );
+                let delay = PHOTO_DELETION_RETRY_DELAY
+                    .saturating_mul(1 << deletion.attempts.min(16))
+                    .min(PHOTO_DELETION_MAX_RETRY_DELAY);
+                photo_dal::postpone_photo_deletion(
+                    state,
+                    deletion.photo_uuid,
+                    format!("{:?}", Utc::now() + Duration::seconds(delay)),
+                    e.to_string(),
+                )
+            }
+        };
+        if let Err(e) = result {
+            println!("failed to update the queued photo deletion : {:?}", e);
+        }
+    }
+}
+
+// One-off command : compare the object store with the Photos table. Objects without a photo and
+// photos missing objects are reported, and removed when remove_orphans is set
+pub async fn reconcile_photos(state: &Arc<AppState>, remove_orphans: bool) {
+    if let Err(e) = reconcile(state, remove_orphans).await {
+        println!("failed to reconcile the photos : {:?}", e);
+    }
+}
+
+async fn reconcile(state: &Arc<AppState>, remove_orphans: bool) -> Result<(), ServiceError> {
+    // Read before listing the objects : objects are stored before their photo is saved, so every
+    // photo read here has its objects listed unless they are really missing
+    let photos = photo_dal::get_all_photos(state)?;
+    let queued_uuids = photo_dal::get_queued_photo_deletions(state)?;
+    let keys = state.object_store.list("").await?;
+
+    // Objects of queued photos are already on their way out
+    let known_uuids = photos
+        .iter()
+        .map(|photo| photo.photo_uuid.as_str())
+        .chain(queued_uuids.iter().map(String::as_str))
+        .collect::<HashSet<&str>>();
+    let grace_limit = Utc::now() - Duration::seconds(PHOTO_RECONCILIATION_GRACE);
+    let mut orphan_object_uuids = BTreeSet::new();
+    let mut orphan_object_bytes = 0;
+    for key in keys.iter() {
+        let photo_uuid = PhotoVariant::photo_uuid(key);
+        if known_uuids.contains(photo_uuid) {
+            continue;
+        }
+        match Uuid::parse_str(photo_uuid) {
+            Ok(uuid) if uuid_datetime(&uuid).is_some_and(|created_at| created_at > grace_limit) => {
+                // Objects are stored before their photo is saved, this may be an upload in progress
             }
-            Err(err) => {
-                println!("error delete photo: {:?}", err);
+            Ok(_) => {
+                // Sized, to tell how much storage removing the orphans frees
+                match state.object_store.head(key).await {
+                    Ok(info) => {
+                        println!("object without photo : {} ({} bytes)", key, info.size);
+                        orphan_object_bytes += info.size;
+                    }
+                    Err(e) => println!("object without photo : {} ({:?})", key, e),
+                }
+                orphan_object_uuids.insert(photo_uuid.to_string());
             }
+            Err(_) => println!("object that is not a photo, left as is : {}", key),
         }
+    }
+
+    let stored_keys = keys.iter().map(String::as_str).collect::<HashSet<&str>>();
+    let orphan_photos = photos
+        .into_iter()
+        .filter(|photo| {
+            let missing_keys = expected_keys(photo)
+                .into_iter()
+                .filter(|key| !stored_keys.contains(key.as_str()))
+                .collect::<Vec<String>>();
+            if !missing_keys.is_empty() {
+                println!(
+                    "photo {} of user {} missing objects : {}",
+                    photo.photo_uuid,
+                    photo.user_uuid,
+                    missing_keys.join(", ")
+                );
+            }
+            !missing_keys.is_empty()
+        })
+        .collect::<Vec<Photo>>();
+
+    println!(
+        "{} objects, {} photos without object, {} objects without photo ({} bytes)",
+        keys.len(),
+        orphan_photos.len(),
+        orphan_object_uuids.len(),
+        orphan_object_bytes
+    );
+    if !remove_orphans {
+        return Ok(());
+    }
+    // Their remaining objects are queued along with them
+    for photo in orphan_photos {
+        photo_dal::delete_photo(state, photo.user_uuid, photo.photo_uuid)?;
+    }
+    photo_dal::queue_photo_deletions(
+        state,
+        &orphan_object_uuids.into_iter().collect::<Vec<String>>(),
+    )?;
+    delete_queued_photos(state).await;
+    println!("orphans removed, failed object deletions stay queued");
+    Ok(())
+}
+
+// Photos that were not reprocessed yet only have their full variant
+fn expected_keys(photo: &Photo) -> Vec<String> {
+    if photo.variants.thumbnail == photo.variants.full {
+        vec![PhotoVariant::Full.key(&photo.photo_uuid)]
     } else {
-        return Err(ServiceError::ForbiddenQuery);
+        PhotoVariant::ALL
+            .iter()
+            .map(|variant| variant.key(&photo.photo_uuid))
+            .collect()
     }
+}
 
-    response_ok(None::<()>)
+// Creation date of a uuid v7
+fn uuid_datetime(uuid: &Uuid) -> Option<DateTime<Utc>> {
+    uuid.get_timestamp().and_then(|timestamp| {
+        let (seconds, nanos) = timestamp.to_unix();
+        DateTime::from_timestamp(seconds as i64, nanos)
+    })
 }
 
 pub async fn switch_photos(
@@ -140,31 +448,331 @@ pub async fn switch_photos(
     State(state): State<Arc<AppState>>,
     Json(request_switch_photo): Json<requests::SwitchPhotosRequest>,
 ) -> Result<(StatusCode, Json<ApiResponse<()>>), ServiceError> {
-    let user_photos = photo_dal::get_user_photos(&state, jwt_claims.user_uuid)?;
-    let mut photos_found = 0;
-    let mut order1 = 0;
-    let mut order2 = 0;
-    for photo in user_photos {
-        if photo.photo_uuid == request_switch_photo.photo_uuid1 {
-            order1 = photo.display_order;
-            photos_found += 1;
-        }
-        if photo.photo_uuid == request_switch_photo.photo_uuid2.clone() {
-            order2 = photo.display_order;
-            photos_found += 1;
-        }
-    }
-    if photos_found == 2 {
-        photo_dal::switch_order_photos(
-            &state,
-            order1,
-            order2,
-            request_switch_photo.photo_uuid1,
-            request_switch_photo.photo_uuid2,
-        )?;
+    let mut photo_uuids = photo_dal::get_user_photos(&state, jwt_claims.user_uuid.clone())?
+        .into_iter()
+        .map(|photo| photo.photo_uuid)
+        .collect::<Vec<String>>();
+    let position1 = photo_uuids
+        .iter()
+        .position(|photo_uuid| *photo_uuid == request_switch_photo.photo_uuid1);
+    let position2 = photo_uuids
+        .iter()
+        .position(|photo_uuid| *photo_uuid == request_switch_photo.photo_uuid2);
+    if let (Some(position1), Some(position2)) = (position1, position2) {
+        photo_uuids.swap(position1, position2);
+        photo_dal::reorder_photos(&state, jwt_claims.user_uuid, &photo_uuids)?;
     } else {
         return Err(ServiceError::ForbiddenQuery);
     }
 
     response_ok(None::<()>)
 }
+
+// Set the order of all the photos of the user at once, photo_uuids going from first to last
+pub async fn reorder_photos(
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+    Json(reorder_request): Json<requests::ReorderPhotosRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<()>>), ServiceError> {
+    let mut user_photo_uuids = photo_dal::get_user_photos(&state, jwt_claims.user_uuid.clone())?
+        .into_iter()
+        .map(|photo| photo.photo_uuid)
+        .collect::<Vec<String>>();
+    let photo_uuids = reorder_request.photo_uuids;
+    if photo_uuids
+        .iter()
+        .any(|photo_uuid| !user_photo_uuids.contains(photo_uuid))
+    {
+        return Err(ServiceError::ForbiddenQuery);
+    }
+    let mut sorted_uuids = photo_uuids.clone();
+    sorted_uuids.sort();
+    user_photo_uuids.sort();
+    if sorted_uuids != user_photo_uuids {
+        return Err(ServiceError::ValueNotAccepted(
+            photo_uuids.join(","),
+            "every photo of the user should be listed once".to_string(),
+        ));
+    }
+
+    photo_dal::reorder_photos(&state, jwt_claims.user_uuid, &photo_uuids)?;
+    response_ok(None::<()>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::my_errors::sqlite_errors::map_sqlite_error;
+    use crate::utilities::test_fixtures::{app_state, insert_user, UserFixture};
+    use crate::utilities::validation::page_offset;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use rusqlite::params;
+    use std::io::Cursor;
+
+    // A photo uploaded before variants existed, its original used for every variant
+    async fn insert_legacy_photo(
+        state: &Arc<AppState>,
+        user_uuid: &str,
+        original: Vec<u8>,
+    ) -> String {
+        let photo_uuid = Uuid::now_v7().to_string();
+        state
+            .object_store
+            .put(&photo_uuid, &PhotoType::Png.to_string(), original)
+            .await
+            .unwrap();
+        let url = state.object_store.public_url(&photo_uuid);
+        state
+            .connection
+            .get()
+            .unwrap()
+            .execute(
+                "INSERT INTO Photos (photo_uuid, user_uuid, url, thumbnail_url, card_url, display_order, status)
+                    VALUES (?, ?, ?, ?, ?, (SELECT COUNT(*) + 1 FROM Photos WHERE user_uuid = ?), 'approved')",
+                params![photo_uuid, user_uuid, url, url, url, user_uuid],
+            )
+            .unwrap();
+        photo_uuid
+    }
+
+    fn new_photo(user_uuid: &str) -> NewPhoto {
+        let photo_uuid = Uuid::now_v7().to_string();
+        NewPhoto {
+            variants: PhotoVariants {
+                thumbnail: format!("{}_thumbnail", photo_uuid),
+                card: format!("{}_card", photo_uuid),
+                full: photo_uuid.clone(),
+            },
+            photo_uuid,
+            user_uuid: user_uuid.to_string(),
+            blurhash: String::new(),
+            perceptual_hash: 0,
+            duplicate_of: None,
+            status: PhotoStatus::Approved,
+        }
+    }
+
+    #[tokio::test]
+    async fn photos_go_after_the_last_one_up_to_the_maximum() {
+        let state = app_state().await;
+        let user_uuid = insert_user(&state, UserFixture::default());
+        for _ in 0..PHOTOS_MAX {
+            photo_dal::create_user_photo(&state, new_photo(&user_uuid)).unwrap();
+        }
+        let photos = photo_dal::get_user_photos(&state, user_uuid.clone()).unwrap();
+        let first_uuid = photos[0].photo_uuid.clone();
+        assert_eq!(
+            photos
+                .iter()
+                .map(|photo| photo.display_order)
+                .collect::<Vec<usize>>(),
+            (1..=PHOTOS_MAX).collect::<Vec<usize>>()
+        );
+        assert!(matches!(
+            photo_dal::create_user_photo(&state, new_photo(&user_uuid)),
+            Err(SqliteError::Conflict)
+        ));
+
+        // A gap left by a deletion is filled at the end
+        photo_dal::delete_photo(&state, user_uuid.clone(), first_uuid).unwrap();
+        let photo = new_photo(&user_uuid);
+        let photo_uuid = photo.photo_uuid.clone();
+        photo_dal::create_user_photo(&state, photo).unwrap();
+        let photos = photo_dal::get_user_photos(&state, user_uuid).unwrap();
+        assert_eq!(photos.last().unwrap().photo_uuid, photo_uuid);
+        assert_eq!(photos.last().unwrap().display_order, PHOTOS_MAX);
+    }
+
+    #[tokio::test]
+    async fn pending_photos_are_listed_by_page() {
+        let state = app_state().await;
+        let mut pending_uuids = Vec::new();
+        for name in ["Ann", "Bea"] {
+            let user_uuid = insert_user(
+                &state,
+                UserFixture {
+                    name,
+                    ..Default::default()
+                },
+            );
+            for status in [
+                PhotoStatus::Pending,
+                PhotoStatus::Approved,
+                PhotoStatus::Pending,
+            ] {
+                let photo = NewPhoto {
+                    status,
+                    ..new_photo(&user_uuid)
+                };
+                if status == PhotoStatus::Pending {
+                    pending_uuids.push(photo.photo_uuid.clone());
+                }
+                photo_dal::create_user_photo(&state, photo).unwrap();
+            }
+        }
+        let page = |limit, offset| {
+            photo_dal::get_photos_by_status(&state, PhotoStatus::Pending, limit, offset)
+                .unwrap()
+                .into_iter()
+                .map(|photo| photo.photo_uuid)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(page(3, 0), pending_uuids[..3]);
+        assert_eq!(page(3, 3), pending_uuids[3..]);
+        assert!(page(3, 6).is_empty());
+        assert!(page_offset(usize::MAX / 2, 3).is_err());
+    }
+
+    #[tokio::test]
+    async fn duplicates_are_found_through_the_hash_bands() {
+        let state = app_state().await;
+        let user_uuid = insert_user(&state, UserFixture::default());
+        let hash = 0x5A3C_96E1_0FF0_C3A5;
+        // One bit flipped in 8 of the 9 bands, no band is left identical but the last one
+        let far = (0..8).fold(hash, |far, band| far ^ (1 << (7 * band + 3)));
+        let mut photo_uuids = Vec::new();
+        for perceptual_hash in [far, far ^ (1 << 60), 0, 0b111] {
+            let photo = NewPhoto {
+                perceptual_hash,
+                ..new_photo(&user_uuid)
+            };
+            photo_uuids.push(photo.photo_uuid.clone());
+            photo_dal::create_user_photo(&state, photo).unwrap();
+        }
+
+        let found = |hash| {
+            find_duplicates(&state, hash)
+                .unwrap()
+                .into_iter()
+                .map(|photo| photo.photo_uuid)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(found(hash), photo_uuids[..1]);
+        // Flat photos don't match each other
+        assert!(found(0b11).is_empty());
+
+        let plan = state
+            .connection
+            .get()
+            .unwrap()
+            .prepare(
+                "EXPLAIN QUERY PLAN SELECT photo_uuid FROM Photos
+                    WHERE (perceptual_hash >> 0) & 127 = 1 OR (perceptual_hash >> 56) & 255 = 1",
+            )
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>("detail"))
+            .unwrap()
+            .collect::<Result<Vec<String>, _>>()
+            .unwrap();
+        assert!(
+            plan.iter()
+                .any(|step| step.contains("photosHashBand8Index")),
+            "{:?}",
+            plan
+        );
+        assert!(
+            !plan.iter().any(|step| step.starts_with("SCAN")),
+            "{:?}",
+            plan
+        );
+    }
+
+    #[tokio::test]
+    async fn a_taken_display_order_is_a_conflict() {
+        let state = app_state().await;
+        let user_uuid = insert_user(&state, UserFixture::default());
+        let insert = || {
+            state.connection.get().unwrap().execute(
+                "INSERT INTO Photos (photo_uuid, user_uuid, url, thumbnail_url, card_url, display_order)
+                    VALUES (?, ?, '', '', '', 1)",
+                params![Uuid::now_v7().to_string(), user_uuid],
+            )
+        };
+        insert().unwrap();
+        assert!(matches!(
+            insert().map_err(map_sqlite_error),
+            Err(SqliteError::Conflict)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reconciliation_only_removes_old_orphans() {
+        let state = app_state().await;
+        let user_uuid = insert_user(&state, UserFixture::default());
+        let put = |key: String| {
+            let state = state.clone();
+            async move {
+                state
+                    .object_store
+                    .put(&key, &PhotoType::Jpeg.to_string(), vec![0])
+                    .await
+                    .unwrap()
+            }
+        };
+        let saved = new_photo(&user_uuid);
+        let saved_uuid = saved.photo_uuid.clone();
+        for variant in PhotoVariant::ALL {
+            put(variant.key(&saved_uuid)).await;
+        }
+        photo_dal::create_user_photo(&state, saved).unwrap();
+        let missing = new_photo(&user_uuid);
+        let missing_uuid = missing.photo_uuid.clone();
+        photo_dal::create_user_photo(&state, missing).unwrap();
+        let created_at = Utc::now() - Duration::seconds(PHOTO_RECONCILIATION_GRACE + 60);
+        let old_orphan_uuid = Uuid::new_v7(uuid::Timestamp::from_unix(
+            uuid::NoContext,
+            created_at.timestamp() as u64,
+            0,
+        ))
+        .to_string();
+        put(old_orphan_uuid.clone()).await;
+        // An upload in progress, its photo is not saved yet
+        let upload_uuid = Uuid::now_v7().to_string();
+        put(upload_uuid.clone()).await;
+
+        reconcile_photos(&state, true).await;
+
+        let keys = state.object_store.list("").await.unwrap();
+        assert!(keys.contains(&saved_uuid) && keys.contains(&upload_uuid));
+        assert!(!keys.contains(&old_orphan_uuid));
+        let photos = photo_dal::get_user_photos(&state, user_uuid).unwrap();
+        assert_eq!(photos.len(), 1);
+        assert_eq!(photos[0].photo_uuid, saved_uuid);
+        assert!(!photos.iter().any(|photo| photo.photo_uuid == missing_uuid));
+    }
+
+    #[tokio::test]
+    async fn photos_that_cant_be_reprocessed_are_only_deleted_on_demand() {
+        let state = app_state().await;
+        let user_uuid = insert_user(&state, UserFixture::default());
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| Rgb([x as u8, y as u8, 0])))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let mut corrupt = png.clone();
+        corrupt.truncate(corrupt.len() / 2);
+        let valid_uuid = insert_legacy_photo(&state, &user_uuid, png).await;
+        let corrupt_uuid = insert_legacy_photo(&state, &user_uuid, corrupt).await;
+
+        reprocess_photos(&state, false).await;
+
+        let photos = photo_dal::get_user_photos(&state, user_uuid.clone()).unwrap();
+        assert_eq!(photos.len(), 2);
+        assert!(state.object_store.get(&corrupt_uuid).await.is_ok());
+        let unprocessed = photo_dal::get_unprocessed_photos(&state).unwrap();
+        assert_eq!(unprocessed.len(), 1);
+        assert_eq!(unprocessed[0].photo_uuid, corrupt_uuid);
+
+        reprocess_photos(&state, true).await;
+
+        let photos = photo_dal::get_user_photos(&state, user_uuid).unwrap();
+        assert_eq!(photos.len(), 1);
+        assert_eq!(photos[0].photo_uuid, valid_uuid);
+        assert!(photos[0].blurhash.is_some());
+        assert!(state.object_store.get(&corrupt_uuid).await.is_err());
+        assert!(photo_dal::get_unprocessed_photos(&state)
+            .unwrap()
+            .is_empty());
+    }
+}
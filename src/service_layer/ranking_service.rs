@@ -0,0 +1,515 @@
+use crate::configs::config::RankingConfig;
+use crate::constants::constants::{
+    AGE_GAP_HALF_SCORE, COMPATIBILITY_AGE, COMPATIBILITY_DISTANCE, COMPATIBILITY_INTERESTS,
+    COMPATIBILITY_RECENCY, NEW_USER_BOOST_DAYS,
+};
+use crate::data_access_layer::user_dal::{Candidate, Compatibility};
+use crate::requests::requests::DiscoveryPreferences;
+use crate::utilities::geo::bucketed_distance;
+use chrono::{DateTime, Duration, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::cmp::Ordering;
+use uuid::Uuid;
+
+// Everything a strategy may need besides the candidate itself. `now` is given rather than read
+// from the clock so that a ranking only depends on its inputs.
+pub struct RankingContext {
+    pub now: DateTime<Utc>,
+    pub search_radius: u16,
+    pub age: u8,                // of the searching user
+    pub interests: Vec<String>, // of the searching user
+    pub soft_preferences: DiscoveryPreferences,
+}
+
+pub trait RankingStrategy: Send + Sync {
+    // Score between 0 and 1, the higher the score the sooner the candidate is shown
+    fn score(&self, candidate: &Candidate, context: &RankingContext) -> f32;
+}
+
+// Recently active users first
+pub struct Recency;
+
+impl RankingStrategy for Recency {
+    fn score(&self, candidate: &Candidate, context: &RankingContext) -> f32 {
+        recency_score(&candidate.lover.last_seen, context.now)
+    }
+}
+
+fn recency_score(last_seen: &str, now: DateTime<Utc>) -> f32 {
+    match DateTime::parse_from_rfc3339(last_seen) {
+        Ok(last_seen) => {
+            let hours_since = (now - last_seen.with_timezone(&Utc)).num_minutes() as f32 / 60.;
+            1. / (1. + hours_since.max(0.) / 24.)
+        }
+        Err(_) => 0.,
+    }
+}
+
+// Closest users first
+pub struct Distance;
+
+impl RankingStrategy for Distance {
+    fn score(&self, candidate: &Candidate, context: &RankingContext) -> f32 {
+        distance_score(candidate.features.distance, context.search_radius)
+    }
+}
+
+fn distance_score(distance: f32, search_radius: u16) -> f32 {
+    (1. - distance / search_radius.max(1) as f32).clamp(0., 1.)
+}
+
+// Share of the swipes received that were likes, smoothed so that users with few swipes sit around 0.5
+pub struct Attractiveness;
+
+impl RankingStrategy for Attractiveness {
+    fn score(&self, candidate: &Candidate, _: &RankingContext) -> f32 {
+        let likes = candidate.features.likes_received as f32;
+        let passes = candidate.features.passes_received as f32;
+        (likes + 1.) / (likes + passes + 2.)
+    }
+}
+
+// Users who already liked the searching user first
+pub struct LikedYou;
+
+impl RankingStrategy for LikedYou {
+    fn score(&self, candidate: &Candidate, _: &RankingContext) -> f32 {
+        if candidate.lover.liked_you {
+            1.
+        } else {
+            0.
+        }
+    }
+}
+
+// Users who super liked the searching user first
+pub struct SuperLikedYou;
+
+impl RankingStrategy for SuperLikedYou {
+    fn score(&self, candidate: &Candidate, _: &RankingContext) -> f32 {
+        if candidate.features.super_liked_you {
+            1.
+        } else {
+            0.
+        }
+    }
+}
+
+// Users created less than NEW_USER_BOOST_DAYS ago first, the account creation date comes from the uuid v7
+pub struct NewUser;
+
+impl RankingStrategy for NewUser {
+    fn score(&self, candidate: &Candidate, context: &RankingContext) -> f32 {
+        let created_at = Uuid::parse_str(&candidate.lover.uuid)
+            .ok()
+            .and_then(|uuid| uuid.get_timestamp())
+            .and_then(|timestamp| {
+                let (seconds, nanos) = timestamp.to_unix();
+                DateTime::from_timestamp(seconds as i64, nanos)
+            });
+        match created_at {
+            Some(created_at) => {
+                let days_since = (context.now - created_at).num_hours() as f32 / 24.;
+                (1. - days_since.max(0.) / NEW_USER_BOOST_DAYS).clamp(0., 1.)
+            }
+            None => 0.,
+        }
+    }
+}
+
+// Most compatible users first, see compatibility
+pub struct Compatible;
+
+impl RankingStrategy for Compatible {
+    fn score(&self, candidate: &Candidate, context: &RankingContext) -> f32 {
+        compatibility(candidate, context).score
+    }
+}
+
+// How well a candidate fits the searching user, along with the details the client can show.
+// Like every strategy it only depends on its inputs, `now` included. The score is sent to the client,
+// so the distance is bucketed first : the exact one would allow trilateration.
+pub fn compatibility(candidate: &Candidate, context: &RankingContext) -> Compatibility {
+    let shared_interests = candidate
+        .lover
+        .profile
+        .interests
+        .iter()
+        .filter(|interest| context.interests.contains(interest))
+        .cloned()
+        .collect::<Vec<String>>();
+    // Sharing every interest of the user with the fewest of them is a perfect match
+    let fewest_interests = context
+        .interests
+        .len()
+        .min(candidate.lover.profile.interests.len());
+    let interests = if fewest_interests == 0 {
+        0.
+    } else {
+        shared_interests.len() as f32 / fewest_interests as f32
+    };
+    let age_gap = (candidate.lover.age as f32 - context.age as f32).abs();
+    let age = 1. / (1. + age_gap / AGE_GAP_HALF_SCORE);
+    let distance = distance_score(
+        bucketed_distance(candidate.features.distance),
+        context.search_radius,
+    );
+    let recency = recency_score(&candidate.lover.last_seen, context.now);
+
+    Compatibility {
+        score: COMPATIBILITY_INTERESTS * interests
+            + COMPATIBILITY_AGE * age
+            + COMPATIBILITY_DISTANCE * distance
+            + COMPATIBILITY_RECENCY * recency,
+        shared_interests,
+        interests,
+        age,
+        distance,
+        recency,
+    }
+}
+
+// Users matching the soft discovery preferences first, the hard ones already excluded the others
+pub struct SoftPreferences;
+
+impl RankingStrategy for SoftPreferences {
+    fn score(&self, candidate: &Candidate, context: &RankingContext) -> f32 {
+        let preferences = &context.soft_preferences;
+        let profile = &candidate.lover.profile;
+        let matches = [
+            preferences
+                .has_photos
+                .map(|_| !candidate.lover.photos.is_empty()),
+            preferences.verified.map(|_| candidate.features.verified),
+            preferences.last_active_within_days.as_ref().map(|days| {
+                DateTime::parse_from_rfc3339(&candidate.lover.last_seen).is_ok_and(|last_seen| {
+                    context.now - last_seen.with_timezone(&Utc) < Duration::days(days.value as i64)
+                })
+            }),
+            preferences.height.as_ref().map(|height| {
+                profile
+                    .height
+                    .is_some_and(|h| height.value.min <= h && h <= height.value.max)
+            }),
+            preferences.languages.as_ref().map(|languages| {
+                languages
+                    .value
+                    .iter()
+                    .any(|language| profile.languages.contains(language))
+            }),
+            preferences.interests.as_ref().map(|interests| {
+                interests
+                    .value
+                    .iter()
+                    .any(|interest| profile.interests.contains(interest))
+            }),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<bool>>();
+        if matches.is_empty() {
+            return 1.;
+        }
+        matches.iter().filter(|matched| **matched).count() as f32 / matches.len() as f32
+    }
+}
+
+// Weighted sum of the strategies scores. A small seeded jitter breaks ties, for a given seed the
+// ranking is deterministic.
+pub struct Ranker {
+    strategies: Vec<(Box<dyn RankingStrategy>, f32)>,
+    jitter: f32,
+    seed: u64,
+}
+
+impl Ranker {
+    pub fn new(config: &RankingConfig) -> Ranker {
+        let strategies: Vec<(Box<dyn RankingStrategy>, f32)> = vec![
+            (Box::new(Recency), config.recency),
+            (Box::new(Distance), config.distance),
+            (Box::new(Attractiveness), config.attractiveness),
+            (Box::new(LikedYou), config.liked_you),
+            (Box::new(SuperLikedYou), config.super_liked_you),
+            (Box::new(NewUser), config.new_user),
+            (Box::new(Compatible), config.compatibility),
+            (Box::new(SoftPreferences), config.soft_preferences),
+        ];
+        Ranker {
+            // A strategy with a weight of 0 is disabled
+            strategies: strategies
+                .into_iter()
+                .filter(|(_, weight)| *weight > 0.)
+                .collect(),
+            jitter: config.jitter,
+            seed: config.seed,
+        }
+    }
+
+    pub fn score(&self, candidate: &Candidate, context: &RankingContext) -> f32 {
+        let score: f32 = self
+            .strategies
+            .iter()
+            .map(|(strategy, weight)| weight * strategy.score(candidate, context))
+            .sum();
+        score + self.jitter * self.noise(&candidate.lover.uuid)
+    }
+
+    // Sort the candidates, best first
+    pub fn rank(&self, candidates: Vec<Candidate>, context: &RankingContext) -> Vec<Candidate> {
+        let mut scored = candidates
+            .into_iter()
+            .map(|candidate| (self.score(&candidate, context), candidate))
+            .collect::<Vec<(f32, Candidate)>>();
+        scored.sort_by(|(score1, candidate1), (score2, candidate2)| {
+            score2
+                .partial_cmp(score1)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| candidate1.lover.uuid.cmp(&candidate2.lover.uuid))
+        });
+        scored.into_iter().map(|(_, candidate)| candidate).collect()
+    }
+
+    // Random value between 0 and 1 that only depends on the seed and the candidate
+    fn noise(&self, candidate_uuid: &str) -> f32 {
+        let candidate_seed = candidate_uuid.bytes().fold(self.seed, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(byte as u64)
+        });
+        StdRng::seed_from_u64(candidate_seed).gen::<f32>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access_layer::user_dal::{PotentialLover, ProfileDetails, RankingFeatures};
+    use crate::requests::requests::{FilterStrength, HeightRange, Preference};
+
+    fn now() -> DateTime<Utc> {
+        "2024-06-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn context() -> RankingContext {
+        RankingContext {
+            now: now(),
+            search_radius: 50,
+            age: 30,
+            interests: vec!["hiking".to_string(), "chess".to_string()],
+            soft_preferences: DiscoveryPreferences::default(),
+        }
+    }
+
+    // Every strategy disabled, tests enable the ones they need
+    fn config() -> RankingConfig {
+        RankingConfig {
+            seed: 42,
+            candidate_pool: 100,
+            jitter: 0.,
+            recency: 0.,
+            distance: 0.,
+            attractiveness: 0.,
+            liked_you: 0.,
+            super_liked_you: 0.,
+            new_user: 0.,
+            compatibility: 0.,
+            soft_preferences: 0.,
+        }
+    }
+
+    // 30 years old, 10 km away, seen an hour before `now`
+    fn candidate(uuid: &str) -> Candidate {
+        Candidate {
+            lover: PotentialLover {
+                uuid: uuid.to_string(),
+                name: uuid.to_string(),
+                last_seen: "2024-06-01T11:00:00Z".to_string(),
+                age: 30,
+                gender: "male".to_string(),
+                description: String::new(),
+                distance: "10 km".to_string(),
+                photos: vec![],
+                legacy_photos: None,
+                liked_you: false,
+                travelling_to: None,
+                profile: ProfileDetails {
+                    interests: vec![],
+                    languages: vec![],
+                    height: None,
+                    job: None,
+                    education: None,
+                    prompts: vec![],
+                },
+                compatibility: Compatibility::default(),
+            },
+            features: RankingFeatures {
+                distance: 10.,
+                verified: false,
+                likes_received: 0,
+                passes_received: 0,
+                super_liked_you: false,
+            },
+        }
+    }
+
+    fn uuids(candidates: &[Candidate]) -> Vec<&str> {
+        candidates
+            .iter()
+            .map(|candidate| candidate.lover.uuid.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn recency_favours_recently_active_users() {
+        let mut recent = candidate("recent");
+        recent.lover.last_seen = "2024-06-01T11:59:00Z".to_string();
+        let mut old = candidate("old");
+        old.lover.last_seen = "2024-05-01T12:00:00Z".to_string();
+        let mut unparsable = candidate("unparsable");
+        unparsable.lover.last_seen = "yesterday".to_string();
+
+        let scores = [&recent, &old, &unparsable].map(|c| Recency.score(c, &context()));
+        assert!(scores[0] > 0.99);
+        assert!(scores[1] < 0.05);
+        assert_eq!(scores[2], 0.);
+    }
+
+    #[test]
+    fn distance_score_is_linear_within_the_search_radius() {
+        let mut far = candidate("far");
+        far.features.distance = 80.;
+
+        assert_eq!(Distance.score(&candidate("near"), &context()), 0.8);
+        assert_eq!(Distance.score(&far, &context()), 0.);
+    }
+
+    #[test]
+    fn attractiveness_is_smoothed_around_one_half() {
+        let mut liked = candidate("liked");
+        liked.features.likes_received = 8;
+        liked.features.passes_received = 0;
+
+        assert_eq!(Attractiveness.score(&candidate("new"), &context()), 0.5);
+        assert_eq!(Attractiveness.score(&liked, &context()), 0.9);
+    }
+
+    #[test]
+    fn new_user_boost_fades_with_the_account_age() {
+        let created = |days: i64| {
+            let created_at = now() - Duration::days(days);
+            let timestamp = uuid::Timestamp::from_unix(
+                uuid::NoContext,
+                created_at.timestamp() as u64,
+                created_at.timestamp_subsec_nanos(),
+            );
+            candidate(&Uuid::new_v7(timestamp).to_string())
+        };
+
+        assert_eq!(NewUser.score(&created(0), &context()), 1.);
+        assert!(NewUser.score(&created(1), &context()) < 1.);
+        assert_eq!(
+            NewUser.score(&created(NEW_USER_BOOST_DAYS as i64 + 1), &context()),
+            0.
+        );
+        assert_eq!(NewUser.score(&candidate("not a uuid"), &context()), 0.);
+    }
+
+    #[test]
+    fn compatibility_shares_interests_with_the_user_having_fewest() {
+        let mut hiker = candidate("hiker");
+        hiker.lover.profile.interests = vec!["hiking".to_string()];
+        let mut stranger = candidate("stranger");
+        stranger.lover.profile.interests = vec!["golf".to_string()];
+        stranger.lover.age = 30 + AGE_GAP_HALF_SCORE as u8;
+
+        let hiker = compatibility(&hiker, &context());
+        assert_eq!(hiker.shared_interests, vec!["hiking"]);
+        assert_eq!(hiker.interests, 1.);
+        assert_eq!(hiker.age, 1.);
+
+        let stranger = compatibility(&stranger, &context());
+        assert!(stranger.shared_interests.is_empty());
+        assert_eq!(stranger.interests, 0.);
+        assert_eq!(stranger.age, 0.5);
+        assert!(hiker.score > stranger.score);
+    }
+
+    #[test]
+    fn compatibility_only_depends_on_the_distance_bucket() {
+        let at = |distance| {
+            let mut candidate = candidate("somewhere");
+            candidate.features.distance = distance;
+            compatibility(&candidate, &context())
+        };
+
+        for (distance1, distance2) in [(9.6, 10.4), (0.1, 0.9)] {
+            let (compatibility1, compatibility2) = (at(distance1), at(distance2));
+            assert_eq!(compatibility1.distance, compatibility2.distance);
+            assert_eq!(compatibility1.score, compatibility2.score);
+        }
+        assert!(at(10.6).distance < at(10.4).distance);
+    }
+
+    #[test]
+    fn soft_preferences_score_the_share_of_matched_preferences() {
+        let mut preferring = context();
+        preferring.soft_preferences = DiscoveryPreferences {
+            verified: Some(FilterStrength::Soft),
+            height: Some(Preference {
+                value: HeightRange { min: 170, max: 190 },
+                strength: FilterStrength::Soft,
+            }),
+            ..Default::default()
+        };
+        let mut tall = candidate("tall");
+        tall.lover.profile.height = Some(180);
+        let mut both = candidate("both");
+        both.lover.profile.height = Some(180);
+        both.features.verified = true;
+
+        assert_eq!(SoftPreferences.score(&candidate("none"), &preferring), 0.);
+        assert_eq!(SoftPreferences.score(&tall, &preferring), 0.5);
+        assert_eq!(SoftPreferences.score(&both, &preferring), 1.);
+        // Without soft preferences nobody is ranked down
+        assert_eq!(SoftPreferences.score(&candidate("none"), &context()), 1.);
+    }
+
+    #[test]
+    fn ranker_sums_the_enabled_strategies() {
+        let mut config = config();
+        config.liked_you = 2.;
+        config.distance = 1.;
+        let mut liked_you = candidate("liked you");
+        liked_you.lover.liked_you = true;
+        liked_you.features.distance = 40.;
+        let close = candidate("close");
+
+        let ranker = Ranker::new(&config);
+        assert_eq!(ranker.strategies.len(), 2);
+        assert_eq!(ranker.score(&close, &context()), 0.8);
+        let ranked = ranker.rank(vec![close, liked_you], &context());
+        assert_eq!(uuids(&ranked), vec!["liked you", "close"]);
+    }
+
+    #[test]
+    fn ranking_is_deterministic_for_a_seed() {
+        let mut config = config();
+        config.jitter = 1.;
+        let candidates = || (0..20).map(|i| candidate(&format!("user {i}"))).collect();
+
+        let ranker = Ranker::new(&config);
+        let first = ranker.rank(candidates(), &context());
+        let second = ranker.rank(candidates(), &context());
+        assert_eq!(uuids(&first), uuids(&second));
+
+        config.seed = 7;
+        let reseeded = Ranker::new(&config).rank(candidates(), &context());
+        assert_ne!(uuids(&first), uuids(&reseeded));
+    }
+
+    #[test]
+    fn ties_are_broken_by_uuid_without_jitter() {
+        let candidates = ["c", "a", "b"].map(candidate).into_iter().collect();
+
+        let ranked = Ranker::new(&config()).rank(candidates, &context());
+        assert_eq!(uuids(&ranked), vec!["a", "b", "c"]);
+    }
+}
@@ -20,6 +20,7 @@ pub struct SseMessage {
 pub enum SseMessageType {
     ChatMessage,
     GreenTickMessage,
+    SuperLike,
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -34,6 +35,11 @@ pub enum MessageData {
     GreenTickMessage {
         uuid_love_room: String,
     },
+    SuperLike {
+        swiper_uuid: String,
+        swiper_name: String,
+        blurred: bool, // the swiper is hidden like in get_likes_received, uuid and name are empty
+    },
 }
 
 struct Guard<'a> {
@@ -47,6 +53,16 @@ impl Drop for Guard<'_> {
     }
 }
 
+// Send a message to the user if they are connected
+pub fn send_to_user(state: &Arc<AppState>, user_uuid: &str, message: SseMessage) {
+    if let Some(sender) = state.txs.lock().unwrap().get(user_uuid) {
+        match sender.send(message) {
+            Ok(_) => (),
+            Err(e) => println!("send sse message failed : {}", e),
+        }
+    }
+}
+
 pub async fn server_side_event_handler(
     State(state): State<Arc<AppState>>,
     Path(user_private_uuid): Path<String>,
@@ -60,7 +76,7 @@ pub async fn server_side_event_handler(
     let stream = async_stream::stream! {
         let _guard = Guard {
             channels: &state.txs,
-            user_uuid: user_uuid
+            user_uuid
         };
 
         while let Ok(msg) = red.recv().await {
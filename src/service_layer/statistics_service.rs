@@ -1,7 +1,10 @@
 use crate::configs::app_state::AppState;
 use crate::data_access_layer;
+use crate::data_access_layer::user_dal::DiscoveryCriteria;
 use crate::requests::requests;
+use crate::requests::requests::SwipeKind;
 use crate::service_layer::auth_service::JwtClaims;
+use crate::service_layer::discovery_service;
 use crate::utilities::responses::{response_ok, ApiResponse};
 use crate::{
     data_access_layer::trace_dal::GetTracesResponse, my_errors::service_errors::ServiceError,
@@ -22,7 +25,8 @@ pub async fn loved_count(
     if jwt_claims.user_uuid != user_uuid {
         return Err(ServiceError::ForbiddenQuery);
     }
-    let swiped_count = data_access_layer::user_dal::swiped_count(&state, jwt_claims.user_uuid, 1)?;
+    let swiped_count =
+        data_access_layer::user_dal::swiped_count(&state, jwt_claims.user_uuid, SwipeKind::Like)?;
     response_ok(Some(swiped_count))
 }
 
@@ -35,7 +39,8 @@ pub async fn rejected_count(
     if jwt_claims.user_uuid != user_uuid {
         return Err(ServiceError::ForbiddenQuery);
     }
-    let swiped_count = data_access_layer::user_dal::swiped_count(&state, jwt_claims.user_uuid, 0)?;
+    let swiped_count =
+        data_access_layer::user_dal::swiped_count(&state, jwt_claims.user_uuid, SwipeKind::Pass)?;
     response_ok(Some(swiped_count))
 }
 
@@ -48,7 +53,8 @@ pub async fn loving_count(
     if jwt_claims.user_uuid != user_uuid {
         return Err(ServiceError::ForbiddenQuery);
     }
-    let swiped_count = data_access_layer::user_dal::swiping_count(&state, jwt_claims.user_uuid, 1)?;
+    let swiped_count =
+        data_access_layer::user_dal::swiping_count(&state, jwt_claims.user_uuid, SwipeKind::Like)?;
     response_ok(Some(swiped_count))
 }
 
@@ -61,7 +67,42 @@ pub async fn rejecting_count(
     if jwt_claims.user_uuid != user_uuid {
         return Err(ServiceError::ForbiddenQuery);
     }
-    let swiped_count = data_access_layer::user_dal::swiping_count(&state, jwt_claims.user_uuid, 0)?;
+    let swiped_count =
+        data_access_layer::user_dal::swiping_count(&state, jwt_claims.user_uuid, SwipeKind::Pass)?;
+    response_ok(Some(swiped_count))
+}
+
+pub async fn super_loved_count(
+    // How many users super loved you
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+    Path(user_uuid): Path<String>,
+) -> Result<(StatusCode, Json<ApiResponse<usize>>), ServiceError> {
+    if jwt_claims.user_uuid != user_uuid {
+        return Err(ServiceError::ForbiddenQuery);
+    }
+    let swiped_count = data_access_layer::user_dal::swiped_count(
+        &state,
+        jwt_claims.user_uuid,
+        SwipeKind::SuperLike,
+    )?;
+    response_ok(Some(swiped_count))
+}
+
+pub async fn super_loving_count(
+    // How many users you super loved
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+    Path(user_uuid): Path<String>,
+) -> Result<(StatusCode, Json<ApiResponse<usize>>), ServiceError> {
+    if jwt_claims.user_uuid != user_uuid {
+        return Err(ServiceError::ForbiddenQuery);
+    }
+    let swiped_count = data_access_layer::user_dal::swiping_count(
+        &state,
+        jwt_claims.user_uuid,
+        SwipeKind::SuperLike,
+    )?;
     response_ok(Some(swiped_count))
 }
 
@@ -74,16 +115,23 @@ pub async fn matching_potential(
     if jwt_claims.user_uuid != user_uuid {
         return Err(ServiceError::ForbiddenQuery);
     }
-    let potential_matches_count = data_access_layer::lover_dal::potential_matches_count(
+    let user = data_access_layer::user_dal::get_user_by_uuid(&state, jwt_claims.user_uuid.clone())?;
+    // The stored preferences along with the deck filters of the query, like discovery_service::get_deck,
+    // so that the count matches what the deck would show
+    let preferences = data_access_layer::preference_dal::get_discovery_preferences(
         &state,
-        jwt_claims.user_uuid,
-        matching_potential_request.looking_for,
-        matching_potential_request.search_radius,
-        matching_potential_request.latitude * std::f32::consts::PI / 180.,
-        matching_potential_request.longitude * std::f32::consts::PI / 180.,
-        matching_potential_request.looking_for_age_min,
-        matching_potential_request.looking_for_age_max,
+        jwt_claims.user_uuid.clone(),
     )?;
+    let preferences =
+        discovery_service::with_deck_filters(preferences, &matching_potential_request.filters())?;
+    let criteria = DiscoveryCriteria {
+        looking_for: matching_potential_request.looking_for,
+        age_min: matching_potential_request.looking_for_age_min,
+        age_max: matching_potential_request.looking_for_age_max,
+        ..DiscoveryCriteria::from_user(&user, &preferences)?
+    };
+    let potential_matches_count =
+        data_access_layer::lover_dal::potential_matches_count(&state, &criteria)?;
     response_ok(Some(potential_matches_count))
 }
 
@@ -0,0 +1,46 @@
+use crate::configs::app_state::AppState;
+use crate::data_access_layer;
+use crate::data_access_layer::flag_dal::FlagReason;
+use crate::my_errors::sqlite_errors::SqliteError;
+use crate::responses::responses::SwipeQuota;
+use std::sync::Arc;
+
+// Swipes left in the rolling 24 hours window, and when the oldest swipe of the window expires
+pub fn swipe_quota(state: &Arc<AppState>, user_uuid: String) -> Result<SwipeQuota, SqliteError> {
+    let (swipes_count, window_end) =
+        data_access_layer::user_dal::swipes_last_day(state, user_uuid)?;
+    Ok(SwipeQuota {
+        daily_quota: state.swipe_config.daily_quota,
+        remaining: state.swipe_config.daily_quota.saturating_sub(swipes_count),
+        reset_at: window_end,
+    })
+}
+
+// Flag the user for review when they swipe faster than a human could, or like everyone they see
+pub fn detect_bot_swipes(state: &Arc<AppState>, user_uuid: &str) {
+    match data_access_layer::user_dal::swipes_since_count(state, user_uuid.to_string(), 1) {
+        Ok(swipes_count) if swipes_count > state.swipe_config.max_swipes_per_second => {
+            flag(state, user_uuid, FlagReason::SwipeCadence)
+        }
+        Ok(_) => (),
+        Err(e) => println!("swipe cadence check failed : {:?}", e),
+    }
+
+    let sample = state.swipe_config.like_ratio_sample;
+    match data_access_layer::user_dal::recent_likes_count(state, user_uuid.to_string(), sample) {
+        Ok((swipes_count, likes_count))
+            if swipes_count >= sample && likes_count == swipes_count =>
+        {
+            flag(state, user_uuid, FlagReason::LikeRatio)
+        }
+        Ok(_) => (),
+        Err(e) => println!("like ratio check failed : {:?}", e),
+    }
+}
+
+pub fn flag(state: &Arc<AppState>, user_uuid: &str, reason: FlagReason) {
+    println!("flagging user {} for review : {:?}", user_uuid, reason);
+    if let Err(e) = data_access_layer::flag_dal::flag_user(state, user_uuid.to_string(), reason) {
+        println!("flagging user {} failed : {:?}", user_uuid, e);
+    }
+}
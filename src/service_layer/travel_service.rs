@@ -0,0 +1,152 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+
+use crate::configs::app_state::AppState;
+use crate::constants::constants::{
+    TRAVEL_EXPIRY_SWEEP_INTERVAL, TRAVEL_LABEL_MAX_LENGTH, TRAVEL_MAX_DAYS,
+};
+use crate::data_access_layer;
+use crate::data_access_layer::user_dal::User;
+use crate::my_errors::service_errors::ServiceError;
+use crate::requests::requests;
+use crate::service_layer::auth_service::JwtClaims;
+use crate::utilities::geo;
+use crate::utilities::responses::{response_ok_with_message, ApiResponse};
+
+pub async fn set_travel(
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+    Path(user_uuid): Path<String>,
+    Json(travel_request): Json<requests::TravelRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<()>>), ServiceError> {
+    if jwt_claims.user_uuid != user_uuid {
+        return Err(ServiceError::ForbiddenQuery);
+    }
+    if travel_request.latitude < -90.0 || travel_request.latitude > 90.0 {
+        return Err(ServiceError::ValueNotAccepted(
+            travel_request.latitude.to_string(),
+            "latitude should be between -90 and +90".to_string(),
+        ));
+    }
+    if travel_request.longitude < -180.0 || travel_request.longitude > 180.0 {
+        return Err(ServiceError::ValueNotAccepted(
+            travel_request.longitude.to_string(),
+            "longitude should be between -180 and +180".to_string(),
+        ));
+    }
+    if travel_request.label.is_empty()
+        || travel_request.label.chars().count() > TRAVEL_LABEL_MAX_LENGTH
+    {
+        return Err(ServiceError::ValueNotAccepted(
+            travel_request.label,
+            format!("label should be 1 to {TRAVEL_LABEL_MAX_LENGTH} characters long"),
+        ));
+    }
+    let expiry = match DateTime::parse_from_rfc3339(&travel_request.expiry) {
+        Ok(expiry) => expiry.with_timezone(&Utc),
+        Err(_) => {
+            return Err(ServiceError::ValueNotAccepted(
+                travel_request.expiry,
+                "expiry should be an ISO8601 datetime".to_string(),
+            ))
+        }
+    };
+    let user = data_access_layer::user_dal::get_user_by_uuid(&state, user_uuid.clone())?;
+    if !user.can_change_location() {
+        return Err(ServiceError::LocationChangeTooFrequent);
+    }
+    let now = Utc::now();
+    if expiry <= now || expiry > now + Duration::days(TRAVEL_MAX_DAYS) {
+        return Err(ServiceError::ValueNotAccepted(
+            travel_request.expiry,
+            format!("expiry should be within the next {TRAVEL_MAX_DAYS} days"),
+        ));
+    }
+
+    let (latitude, longitude) =
+        travel_location(&user, travel_request.latitude, travel_request.longitude);
+    data_access_layer::user_dal::set_travel(
+        &state,
+        user_uuid,
+        latitude,
+        longitude,
+        travel_request.label,
+        format!("{:?}", expiry),
+    )?;
+    response_ok_with_message(None::<()>, "travel location set".to_string())
+}
+
+// Travel location to store, in radians. A user fuzzing their location gets the same fixed offset as
+// at home : a new offset for each travel could be averaged out, no offset could be trilaterated
+fn travel_location(user: &User, latitude: f32, longitude: f32) -> (f32, f32) {
+    let (latitude, longitude) = (latitude.to_radians(), longitude.to_radians());
+    match user.location_offset_north.zip(user.location_offset_east) {
+        Some((north, east)) => geo::shift(latitude, longitude, north, east),
+        None => (latitude, longitude),
+    }
+}
+
+pub async fn cancel_travel(
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+    Path(user_uuid): Path<String>,
+) -> Result<(StatusCode, Json<ApiResponse<()>>), ServiceError> {
+    if jwt_claims.user_uuid != user_uuid {
+        return Err(ServiceError::ForbiddenQuery);
+    }
+    data_access_layer::user_dal::clear_travel(&state, user_uuid)?;
+    response_ok_with_message(None::<()>, "back to home location".to_string())
+}
+
+// Runs for the whole server lifetime. Searches already ignore an expired travel,
+// this moves the user back home for the other users too
+pub async fn expire_travels(state: Arc<AppState>) {
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(TRAVEL_EXPIRY_SWEEP_INTERVAL));
+    loop {
+        interval.tick().await;
+        if let Err(e) = data_access_layer::user_dal::clear_expired_travels(&state) {
+            println!("clearing expired travels failed : {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::constants::LOCATION_FUZZ_RADIUS;
+    use crate::data_access_layer::user_dal::get_user_by_uuid;
+    use crate::utilities::test_fixtures::{app_state, insert_user, UserFixture};
+
+    #[tokio::test]
+    async fn travels_keep_the_location_offset() {
+        let state = app_state().await;
+        let user_uuid = insert_user(&state, UserFixture::default());
+        let user = get_user_by_uuid(&state, user_uuid.clone()).unwrap();
+        // Rome, stored as is without fuzzing
+        let rome = (41.9028_f32.to_radians(), 12.4964_f32.to_radians());
+        assert_eq!(travel_location(&user, 41.9028, 12.4964), rome);
+
+        let (north, east) = geo::random_offset(&mut rand::thread_rng(), LOCATION_FUZZ_RADIUS);
+        state
+            .connection
+            .get()
+            .unwrap()
+            .execute(
+                "UPDATE Users SET location_offset_north = ?, location_offset_east = ? WHERE user_uuid = ?",
+                rusqlite::params![north, east, user_uuid],
+            )
+            .unwrap();
+        let user = get_user_by_uuid(&state, user_uuid).unwrap();
+        // The same offset as at home, whatever the travel
+        assert_eq!(
+            travel_location(&user, 41.9028, 12.4964),
+            geo::shift(rome.0, rome.1, north, east)
+        );
+    }
+}
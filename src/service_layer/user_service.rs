@@ -7,27 +7,63 @@ use axum::{
     http::StatusCode,
     Json,
 };
+use chrono::{DateTime, NaiveDate, Utc};
 use rand::thread_rng;
 use std::sync::Arc;
 
 use crate::configs::app_state::AppState;
 use crate::data_access_layer;
+use crate::data_access_layer::flag_dal::FlagReason;
+use crate::data_access_layer::photo_dal::LegacyPhotoFields;
 use crate::data_access_layer::user_dal::User;
 use crate::my_errors::service_errors::ServiceError;
 use crate::my_errors::sqlite_errors::{transaction_error, SqliteError};
 use crate::requests::requests;
+use crate::requests::requests::SwipeKind;
 use crate::responses::responses;
 use crate::service_layer::auth_service::JwtClaims;
+use crate::service_layer::discovery_service;
+use crate::service_layer::sse_service;
+use crate::service_layer::sse_service::{MessageData, SseMessage, SseMessageType};
+use crate::service_layer::swipe_limits_service;
+use crate::utilities::api_version::ApiVersion;
+use crate::utilities::geo;
 use crate::utilities::responses::{response_ok, response_ok_with_message, ApiResponse};
+use crate::utilities::validation::check_choices;
 use crate::{
-    constants::constants::{M_COST, OUTPUT_LEN, P_COST, T_COST},
-    data_access_layer::user_dal::PotentialLover,
+    constants::constants::{
+        HEIGHT_MAX, HEIGHT_MIN, LOCATION_FUZZ_RADIUS, LOCATION_UPDATE_INTERVAL, MAXIMUM_AGE,
+        MINIMUM_AGE, M_COST, OUTPUT_LEN, PROFILE_ANSWER_MAX_LENGTH, PROFILE_INTERESTS,
+        PROFILE_INTERESTS_MAX, PROFILE_LANGUAGES, PROFILE_LANGUAGES_MAX, PROFILE_PROMPTS,
+        PROFILE_PROMPTS_MAX, PROFILE_TEXT_MAX_LENGTH, P_COST, SAME_LOCATION_EPSILON, T_COST,
+    },
+    data_access_layer::user_dal::DiscoveryCriteria,
 };
 
 pub async fn create_user(
     State(state): State<Arc<AppState>>,
     Json(mut create_user_request): Json<requests::CreateUserRequest>,
 ) -> Result<(StatusCode, Json<ApiResponse<()>>), ServiceError> {
+    let birthdate = match NaiveDate::parse_from_str(&create_user_request.birthdate, "%Y-%m-%d") {
+        Ok(birthdate) => birthdate,
+        Err(_) => {
+            return Err(ServiceError::ValueNotAccepted(
+                create_user_request.birthdate,
+                "birthdate should be an ISO8601 date, example : 1990-02-14".to_string(),
+            ))
+        }
+    };
+    match Utc::now().date_naive().years_since(birthdate) {
+        Some(age) if (MINIMUM_AGE..=MAXIMUM_AGE).contains(&age) => {}
+        _ => {
+            return Err(ServiceError::ValueNotAccepted(
+                create_user_request.birthdate,
+                format!("age should be between {MINIMUM_AGE} and {MAXIMUM_AGE}"),
+            ))
+        }
+    }
+    create_user_request.birthdate = birthdate.to_string();
+
     let user = data_access_layer::user_dal::get_user_by_email(
         &state,
         create_user_request.email.to_string(),
@@ -57,6 +93,7 @@ pub async fn create_user(
 
 pub async fn get_user(
     jwt_claims: JwtClaims,
+    api_version: ApiVersion,
     State(state): State<Arc<AppState>>,
     Path(user_uuid): Path<String>,
 ) -> Result<(StatusCode, Json<ApiResponse<User>>), ServiceError> {
@@ -66,6 +103,13 @@ pub async fn get_user(
     let mut user_found = data_access_layer::user_dal::get_user_by_uuid(&state, user_uuid)?;
     user_found.latitude = user_found.latitude / std::f32::consts::PI * 180.;
     user_found.longitude = user_found.longitude / std::f32::consts::PI * 180.;
+    user_found.travel_latitude = user_found
+        .travel_latitude
+        .map(|latitude| latitude / std::f32::consts::PI * 180.);
+    user_found.travel_longitude = user_found
+        .travel_longitude
+        .map(|longitude| longitude / std::f32::consts::PI * 180.);
+    user_found.legacy_photos = LegacyPhotoFields::for_version(api_version, &user_found.photos);
     response_ok(Some(user_found))
 }
 
@@ -131,38 +175,160 @@ pub async fn update_user(
     }
     update_user_request.longitude = update_user_request.longitude * std::f32::consts::PI / 180.;
     update_user_request.latitude = update_user_request.latitude * std::f32::consts::PI / 180.;
+
+    let user = data_access_layer::user_dal::get_user_by_uuid(&state, user_uuid)?;
+    let previous_offset = user.location_offset_north.zip(user.location_offset_east);
+    let location_offset = match (update_user_request.fuzz_location, previous_offset) {
+        (false, _) => None,
+        (true, Some(offset)) => Some(offset),
+        (true, None) => Some(geo::random_offset(
+            &mut rand::thread_rng(),
+            LOCATION_FUZZ_RADIUS,
+        )),
+    };
+    let with_offset = |(latitude, longitude): (f32, f32), offset: Option<(f32, f32)>| match offset {
+        Some((north, east)) => geo::shift(latitude, longitude, north, east),
+        None => (latitude, longitude),
+    };
+    // Clients send the stored location back when the user did not move
+    let moved = (update_user_request.latitude - user.latitude).abs() > SAME_LOCATION_EPSILON
+        || (update_user_request.longitude - user.longitude).abs() > SAME_LOCATION_EPSILON;
+    let mut location_updated_at = user.location_updated_at.clone();
+    let mut message = "user updated successfully".to_string();
+    let (latitude, longitude) = if moved && user.can_change_location() {
+        location_updated_at = format!("{:?}", Utc::now());
+        with_offset(
+            (update_user_request.latitude, update_user_request.longitude),
+            location_offset,
+        )
+    } else {
+        if moved {
+            message = format!(
+                "user updated, location kept : it can only change once every {} minutes",
+                LOCATION_UPDATE_INTERVAL / 60
+            );
+        }
+        if location_offset == previous_offset {
+            (user.latitude, user.longitude)
+        } else {
+            // Fuzzing was switched on or off, the offset moves from the stored location
+            let unfuzzed = with_offset(
+                (user.latitude, user.longitude),
+                previous_offset.map(|(north, east)| (-north, -east)),
+            );
+            with_offset(unfuzzed, location_offset)
+        }
+    };
+    update_user_request.latitude = latitude;
+    update_user_request.longitude = longitude;
     // todo : check updated email is not taken
-    data_access_layer::user_dal::update_user_infos(&state, update_user_request)?;
-    response_ok_with_message(None::<()>, "user updated successfully".to_string())
+    data_access_layer::user_dal::update_user_infos(
+        &state,
+        update_user_request,
+        location_offset,
+        location_updated_at,
+    )?;
+    response_ok_with_message(None::<()>, message)
+}
+
+pub async fn update_profile(
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+    Path(user_uuid): Path<String>,
+    Json(mut update_profile_request): Json<requests::UpdateProfileRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<()>>), ServiceError> {
+    if jwt_claims.user_uuid != user_uuid {
+        return Err(ServiceError::ForbiddenQuery);
+    }
+    check_choices(
+        &update_profile_request.interests,
+        PROFILE_INTERESTS,
+        PROFILE_INTERESTS_MAX,
+        "interests",
+    )?;
+    check_choices(
+        &update_profile_request.languages,
+        PROFILE_LANGUAGES,
+        PROFILE_LANGUAGES_MAX,
+        "languages",
+    )?;
+    if let Some(height) = update_profile_request.height {
+        if !(HEIGHT_MIN..=HEIGHT_MAX).contains(&height) {
+            return Err(ServiceError::ValueNotAccepted(
+                height.to_string(),
+                format!("height should be between {HEIGHT_MIN} and {HEIGHT_MAX} cm"),
+            ));
+        }
+    }
+    // Blank texts are removed rather than stored
+    update_profile_request.job = update_profile_request
+        .job
+        .filter(|job| !job.trim().is_empty());
+    update_profile_request.education = update_profile_request
+        .education
+        .filter(|education| !education.trim().is_empty());
+    for text in [
+        &update_profile_request.job,
+        &update_profile_request.education,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if text.chars().count() > PROFILE_TEXT_MAX_LENGTH {
+            return Err(ServiceError::ValueNotAccepted(
+                text.to_string(),
+                format!(
+                    "job and education should be at most {PROFILE_TEXT_MAX_LENGTH} characters long"
+                ),
+            ));
+        }
+    }
+    let questions = update_profile_request
+        .prompts
+        .iter()
+        .map(|prompt| prompt.question.clone())
+        .collect::<Vec<String>>();
+    check_choices(&questions, PROFILE_PROMPTS, PROFILE_PROMPTS_MAX, "prompts")?;
+    for prompt in update_profile_request.prompts.iter() {
+        let length = prompt.answer.trim().chars().count();
+        if length == 0 || length > PROFILE_ANSWER_MAX_LENGTH {
+            return Err(ServiceError::ValueNotAccepted(
+                prompt.answer.clone(),
+                format!("answers should be 1 to {PROFILE_ANSWER_MAX_LENGTH} characters long"),
+            ));
+        }
+    }
+
+    data_access_layer::user_dal::update_profile_details(&state, user_uuid, update_profile_request)?;
+    response_ok_with_message(None::<()>, "profile updated successfully".to_string())
 }
 
 pub async fn find_lover(
     jwt_claims: JwtClaims,
+    api_version: ApiVersion,
     State(state): State<Arc<AppState>>,
-) -> Result<(StatusCode, Json<ApiResponse<PotentialLover>>), ServiceError> {
+) -> Result<(StatusCode, Json<ApiResponse<responses::FindLoverResponse>>), ServiceError> {
     let user = data_access_layer::user_dal::get_user_by_uuid(&state, jwt_claims.user_uuid.clone())?;
-    let potential_lover = data_access_layer::user_dal::find_love_target(
+    let preferences = data_access_layer::preference_dal::get_discovery_preferences(
         &state,
-        jwt_claims.user_uuid,
-        user.looking_for,
-        user.search_radius,
-        user.latitude,
-        user.longitude,
-        user.looking_for_age_min,
-        user.looking_for_age_max,
-    );
+        jwt_claims.user_uuid.clone(),
+    )?;
+    let criteria = DiscoveryCriteria::from_user(&user, &preferences)?;
+    let mut potential_lover = discovery_service::serve_cards(&state, &criteria, 1)?
+        .pop()
+        .ok_or(ServiceError::NoPotentialMatchFound)?;
+    potential_lover.legacy_photos =
+        LegacyPhotoFields::for_version(api_version, &potential_lover.photos);
 
-    match potential_lover {
-        Ok(user) => {
-            response_ok_with_message(Some(user), "you found a potential lover !".to_string())
+    let response = if api_version.bare_potential_lover() {
+        responses::FindLoverResponse::PotentialLover(potential_lover)
+    } else {
+        responses::FindLoverResponse::WithSwipeQuota {
+            potential_lover,
+            swipe_quota: swipe_limits_service::swipe_quota(&state, jwt_claims.user_uuid)?,
         }
-        Err(err) => {
-            match err {
-                SqliteError::NotFound => Err(ServiceError::NoPotentialMatchFound), // TODO response for this
-                _ => Err(ServiceError::Sqlite(err)),
-            }
-        }
-    }
+    };
+    response_ok_with_message(Some(response), "you found a potential lover !".to_string())
 }
 
 pub async fn swipe_user(
@@ -174,6 +340,32 @@ pub async fn swipe_user(
         // Cannot swipe yourself..
         return Err(ServiceError::ForbiddenQuery);
     }
+    if swipe_user_request.kind == SwipeKind::SuperLike
+        && data_access_layer::user_dal::super_likes_last_day_count(
+            &state,
+            jwt_claims.user_uuid.clone(),
+        )? >= state.swipe_config.super_like_daily_quota
+    {
+        return Err(ServiceError::SuperLikeQuotaExceeded);
+    }
+    let swipe_quota = swipe_limits_service::swipe_quota(&state, jwt_claims.user_uuid.clone())?;
+    if swipe_quota.remaining == 0 {
+        return Err(ServiceError::SwipeQuotaExceeded(swipe_quota));
+    }
+    // Users who liked the swiper are swiped from the likes received, not from the deck
+    if !discovery_service::consume_card(
+        &state,
+        &jwt_claims.user_uuid,
+        &swipe_user_request.swiped_uuid,
+    ) && data_access_layer::lover_dal::like_received_position(
+        &state,
+        jwt_claims.user_uuid.clone(),
+        swipe_user_request.swiped_uuid.clone(),
+    )?
+    .is_none()
+    {
+        swipe_limits_service::flag(&state, &jwt_claims.user_uuid, FlagReason::OffDeckSwipe);
+    }
 
     // todo : refactor transaction here
     state
@@ -186,9 +378,17 @@ pub async fn swipe_user(
         &state,
         jwt_claims.user_uuid.clone(),
         swipe_user_request.swiped_uuid.clone(),
-        u8::from(swipe_user_request.love),
+        swipe_user_request.kind,
     ) {
         Ok(()) => {
+            swipe_limits_service::detect_bot_swipes(&state, &jwt_claims.user_uuid);
+            if swipe_user_request.kind == SwipeKind::SuperLike {
+                notify_super_like(
+                    &state,
+                    jwt_claims.user_uuid.clone(),
+                    &swipe_user_request.swiped_uuid,
+                );
+            }
             match data_access_layer::user_dal::check_mutual_love(
                 &state,
                 jwt_claims.user_uuid.clone(),
@@ -253,7 +453,131 @@ pub async fn swipe_user(
                 .unwrap()
                 .execute("END TRANSACTION", [])
                 .map_err(transaction_error)?;
-            Err(ServiceError::Sqlite(err))
+            match err {
+                SqliteError::Conflict => Err(ServiceError::ValueNotAccepted(
+                    swipe_user_request.swiped_uuid,
+                    "already swiped".to_string(),
+                )),
+                err => Err(ServiceError::Sqlite(err)),
+            }
         }
     }
 }
+
+// Tell the super liked user in real time who super liked them, unless get_likes_received would blur
+// that like : the notification is then sent without the swiper
+fn notify_super_like(state: &Arc<AppState>, swiper_uuid: String, swiped_uuid: &str) {
+    let in_clear = match data_access_layer::lover_dal::like_received_position(
+        state,
+        swiped_uuid.to_string(),
+        swiper_uuid.clone(),
+    ) {
+        Ok(position) => position.is_some_and(|position| position < state.likes_received_visible),
+        Err(e) => {
+            println!("super like notification failed : {:?}", e);
+            return;
+        }
+    };
+    let data = if in_clear {
+        match data_access_layer::user_dal::get_user_by_uuid(state, swiper_uuid) {
+            Ok(swiper) => MessageData::SuperLike {
+                swiper_uuid: swiper.uuid,
+                swiper_name: swiper.name,
+                blurred: false,
+            },
+            Err(e) => {
+                println!("super like notification failed : {:?}", e);
+                return;
+            }
+        }
+    } else {
+        MessageData::SuperLike {
+            swiper_uuid: "".to_string(),
+            swiper_name: "".to_string(),
+            blurred: true,
+        }
+    };
+    sse_service::send_to_user(
+        state,
+        swiped_uuid,
+        SseMessage {
+            message_type: SseMessageType::SuperLike,
+            data,
+        },
+    );
+}
+
+// Revert the last swipe of the user if it is recent enough, and put the swiped user back in the deck
+pub async fn undo_swipe(
+    jwt_claims: JwtClaims,
+    State(state): State<Arc<AppState>>,
+) -> Result<(StatusCode, Json<ApiResponse<responses::UndoSwipeResponse>>), ServiceError> {
+    let swipe =
+        match data_access_layer::user_dal::get_last_swipe(&state, jwt_claims.user_uuid.clone()) {
+            Ok(swipe) => swipe,
+            Err(SqliteError::NotFound) => {
+                return Err(ServiceError::UndoNotAllowed(
+                    "there is no swipe to undo".to_string(),
+                ))
+            }
+            Err(err) => return Err(ServiceError::Sqlite(err)),
+        };
+    let swipe_datetime =
+        DateTime::parse_from_rfc3339(&swipe.swipe_datetime).map_err(|_| ServiceError::Internal)?;
+    if (Utc::now() - swipe_datetime.with_timezone(&Utc)).num_seconds()
+        > state.swipe_config.undo_window
+    {
+        return Err(ServiceError::UndoNotAllowed(
+            "the last swipe is too old".to_string(),
+        ));
+    }
+    let undos_count =
+        data_access_layer::user_dal::undos_last_day_count(&state, jwt_claims.user_uuid.clone())?;
+    if undos_count >= state.swipe_config.undo_daily_quota {
+        return Err(ServiceError::UndoNotAllowed(
+            "daily undo quota reached".to_string(),
+        ));
+    }
+
+    let love_uuid = if swipe.love >= 1 {
+        match data_access_layer::lover_dal::get_love_interaction(
+            &state,
+            jwt_claims.user_uuid.clone(),
+            swipe.swiped.clone(),
+        ) {
+            Ok((love_uuid, interacted)) => {
+                if !state.swipe_config.undo_tears_down_match {
+                    return Err(ServiceError::UndoNotAllowed(
+                        "the swipe created a match".to_string(),
+                    ));
+                }
+                if interacted {
+                    return Err(ServiceError::UndoNotAllowed(
+                        "your match already interacted with you".to_string(),
+                    ));
+                }
+                Some(love_uuid)
+            }
+            Err(SqliteError::NotFound) => None, // the swipe didn't create a match
+            Err(err) => return Err(ServiceError::Sqlite(err)),
+        }
+    } else {
+        None
+    };
+
+    data_access_layer::user_dal::undo_swipe(
+        &state,
+        jwt_claims.user_uuid.clone(),
+        &swipe,
+        love_uuid,
+    )?;
+    discovery_service::restore_card(&state, &jwt_claims.user_uuid, swipe.swiped.clone());
+
+    response_ok_with_message(
+        Some(responses::UndoSwipeResponse {
+            restored_uuid: swipe.swiped,
+            undos_left: state.swipe_config.undo_daily_quota - undos_count - 1,
+        }),
+        "swipe undone".to_string(),
+    )
+}
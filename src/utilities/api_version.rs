@@ -0,0 +1,42 @@
+use crate::constants::constants::{API_VERSION_HEADER, API_VERSION_LATEST};
+use crate::my_errors::service_errors::ServiceError;
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+
+// Version of the api a client was written for, sent in the api-version header.
+// Clients that don't send it get version 1. Fields replaced in a version are still sent to older clients
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApiVersion(pub u8);
+
+impl ApiVersion {
+    // Version 2 replaced the comma separated photo strings by photo lists
+    pub fn legacy_photo_fields(&self) -> bool {
+        self.0 < 2
+    }
+
+    // Version 2 sends the swipe quota along the potential lover of findlover
+    pub fn bare_potential_lover(&self) -> bool {
+        self.0 < 2
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiVersion {
+    type Rejection = ServiceError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let header = match parts.headers.get(API_VERSION_HEADER) {
+            Some(header) => header,
+            None => return Ok(ApiVersion(1)),
+        };
+        header
+            .to_str()
+            .ok()
+            .and_then(|version| version.parse::<u8>().ok())
+            .filter(|version| (1..=API_VERSION_LATEST).contains(version))
+            .map(ApiVersion)
+            .ok_or(ServiceError::ValueNotAccepted(
+                format!("{:?}", header),
+                format!("{API_VERSION_HEADER} should be 1 to {API_VERSION_LATEST}"),
+            ))
+    }
+}
@@ -0,0 +1,204 @@
+use rand::Rng;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+pub const EARTH_RADIUS: f32 = 6371.; // km
+
+// Latitude and longitude bounds, in radians, of every point closer than `radius` km from a location.
+// Used to prefilter users through the UsersLocations R*Tree before computing exact distances.
+// http://janmatuschek.de/LatitudeLongitudeBoundingCoordinates
+pub struct BoundingBox {
+    pub min_latitude: f32,
+    pub max_latitude: f32,
+    pub min_longitude: f32,
+    pub max_longitude: f32,
+}
+
+impl BoundingBox {
+    pub fn around(latitude: f32, longitude: f32, radius: f32) -> BoundingBox {
+        // A few meters of margin so that f32 rounding never drops a user the exact distance would keep
+        let angular_radius = radius / EARTH_RADIUS + 1e-5;
+        let min_latitude = latitude - angular_radius;
+        let max_latitude = latitude + angular_radius;
+
+        if min_latitude <= -FRAC_PI_2 || max_latitude >= FRAC_PI_2 {
+            // A pole is within the radius, every longitude is
+            return BoundingBox {
+                min_latitude: min_latitude.max(-FRAC_PI_2),
+                max_latitude: max_latitude.min(FRAC_PI_2),
+                min_longitude: -PI,
+                max_longitude: PI,
+            };
+        }
+
+        let delta_longitude = (angular_radius.sin() / latitude.cos()).asin();
+        let min_longitude = longitude - delta_longitude;
+        let max_longitude = longitude + delta_longitude;
+        if min_longitude < -PI || max_longitude > PI {
+            // The box crosses the antimeridian, keep every longitude rather than two boxes
+            return BoundingBox {
+                min_latitude,
+                max_latitude,
+                min_longitude: -PI,
+                max_longitude: PI,
+            };
+        }
+
+        BoundingBox {
+            min_latitude,
+            max_latitude,
+            min_longitude,
+            max_longitude,
+        }
+    }
+}
+
+// What other users are shown instead of the exact distance, which would allow trilateration
+pub fn distance_label(distance: f32) -> String {
+    if distance < 1. {
+        "<1 km".to_string()
+    } else {
+        format!("{} km", bucketed_distance(distance) as u32)
+    }
+}
+
+// The distance in km as precise as distance_label, for anything derived from it and sent to other users
+pub fn bucketed_distance(distance: f32) -> f32 {
+    if distance < 1. {
+        0.
+    } else {
+        distance.round()
+    }
+}
+
+// Random (north, east) offset in km, between half the radius and the radius so that it always hides something
+pub fn random_offset(rng: &mut impl Rng, radius: f32) -> (f32, f32) {
+    let bearing = rng.gen_range(0. ..2. * PI);
+    let distance = rng.gen_range(radius / 2. ..radius);
+    (distance * bearing.cos(), distance * bearing.sin())
+}
+
+// Moves a location (radians) by `north` and `east` km
+pub fn shift(latitude: f32, longitude: f32, north: f32, east: f32) -> (f32, f32) {
+    let shifted_latitude = (latitude + north / EARTH_RADIUS).clamp(-FRAC_PI_2, FRAC_PI_2);
+    let shifted_longitude = longitude + east / (EARTH_RADIUS * latitude.cos().max(1e-3));
+    let wrapped_longitude = if shifted_longitude > PI {
+        shifted_longitude - 2. * PI
+    } else if shifted_longitude < -PI {
+        shifted_longitude + 2. * PI
+    } else {
+        shifted_longitude
+    };
+    (shifted_latitude, wrapped_longitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::constants::LOCATION_FUZZ_RADIUS;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    // Great circle distance in km between two locations (radians), in f64 since f32 can't tell
+    // apart points a few hundred meters away with this formula
+    fn distance((latitude1, longitude1): (f32, f32), (latitude2, longitude2): (f32, f32)) -> f32 {
+        let (latitude1, longitude1) = (latitude1 as f64, longitude1 as f64);
+        let (latitude2, longitude2) = (latitude2 as f64, longitude2 as f64);
+        let cos_angle = latitude1.sin() * latitude2.sin()
+            + latitude1.cos() * latitude2.cos() * (longitude1 - longitude2).cos();
+        (EARTH_RADIUS as f64 * cos_angle.clamp(-1., 1.).acos()) as f32
+    }
+
+    // The distance an attacker reads from a label, the middle of its bucket
+    fn label_distance(label: &str) -> f32 {
+        match label {
+            "<1 km" => 0.5,
+            label => label.trim_end_matches(" km").parse().unwrap(),
+        }
+    }
+
+    // The location whose distances best fit the labels shown to an attacker at each of `observers`,
+    // searched on a grid around the first observer, then refined
+    fn trilaterate(observers: &[(f32, f32)], labels: &[String]) -> (f32, f32) {
+        let error = |location: (f32, f32)| -> f32 {
+            observers
+                .iter()
+                .zip(labels)
+                .map(|(observer, label)| {
+                    (distance(*observer, location) - label_distance(label)).powi(2)
+                })
+                .sum()
+        };
+        let mut best = observers[0];
+        for (half_side, step) in [(40., 1.), (2., 0.05)] {
+            let center = best;
+            let steps = (half_side / step) as i32;
+            for north in -steps..=steps {
+                for east in -steps..=steps {
+                    let location =
+                        shift(center.0, center.1, north as f32 * step, east as f32 * step);
+                    if error(location) < error(best) {
+                        best = location;
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    // Someone in Paris, seen by an attacker spoofing 12 locations 5 to 30 km around
+    fn trilateration_error(rng: &mut StdRng, fuzz: bool) -> (f32, f32) {
+        let home = (48.8566_f32.to_radians(), 2.3522_f32.to_radians());
+        let stored = if fuzz {
+            let (north, east) = random_offset(rng, LOCATION_FUZZ_RADIUS);
+            shift(home.0, home.1, north, east)
+        } else {
+            home
+        };
+        let observers = (0..12)
+            .map(|_| {
+                let bearing = rng.gen_range(0. ..2. * PI);
+                let range = rng.gen_range(5. ..30.);
+                shift(home.0, home.1, range * bearing.cos(), range * bearing.sin())
+            })
+            .collect::<Vec<(f32, f32)>>();
+        let labels = observers
+            .iter()
+            .map(|observer| distance_label(distance(*observer, stored)))
+            .collect::<Vec<String>>();
+        let estimate = trilaterate(&observers, &labels);
+        (distance(estimate, home), distance(estimate, stored))
+    }
+
+    #[test]
+    fn bucketed_distances_alone_locate_a_user() {
+        let mut rng = StdRng::seed_from_u64(36);
+        for _ in 0..10 {
+            let (home_error, _) = trilateration_error(&mut rng, false);
+            assert!(home_error < 1., "found {home_error} km away");
+        }
+    }
+
+    #[test]
+    fn trilateration_only_finds_the_fuzzed_location() {
+        let mut rng = StdRng::seed_from_u64(36);
+        for _ in 0..20 {
+            let (home_error, stored_error) = trilateration_error(&mut rng, true);
+            assert!(stored_error < 1., "found {stored_error} km away");
+            // The stored location is at least half the fuzz radius from home, and the attacker
+            // finds it no closer than stored_error
+            assert!(
+                home_error >= LOCATION_FUZZ_RADIUS / 2. - stored_error,
+                "found {home_error} km from home, {stored_error} km from the stored location"
+            );
+        }
+    }
+
+    #[test]
+    fn offsets_are_between_half_the_radius_and_the_radius() {
+        let mut rng = StdRng::seed_from_u64(36);
+        for _ in 0..1000 {
+            let (north, east) = random_offset(&mut rng, LOCATION_FUZZ_RADIUS);
+            let length = north.hypot(east);
+            assert!((LOCATION_FUZZ_RADIUS / 2. ..LOCATION_FUZZ_RADIUS).contains(&length));
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::constants::constants::{
+    PHOTO_BLURHASH_COMPONENTS, PHOTO_BLURHASH_SIZE, PHOTO_CARD_SIZE, PHOTO_FULL_SIZE,
+    PHOTO_HASH_MIN_BITS, PHOTO_JPEG_QUALITY, PHOTO_MAX_DECODED_SIZE, PHOTO_MAX_DIMENSION,
+    PHOTO_THUMBNAIL_SIZE,
+};
+use crate::my_errors::service_errors::ServiceError;
+use image::codecs::jpeg::JpegEncoder;
+use image::error::{ImageError, LimitErrorKind};
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+use std::fmt;
+use std::io::Cursor;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PhotoType {
+    Png,
+    Jpeg,
+}
+
+impl PhotoType {
+    // The type is read from the magic bytes, whatever the client claims it to be
+    pub fn sniff(data: &[u8]) -> Result<PhotoType, ServiceError> {
+        match image::guess_format(data) {
+            Ok(ImageFormat::Png) => Ok(PhotoType::Png),
+            Ok(ImageFormat::Jpeg) => Ok(PhotoType::Jpeg),
+            _ => Err(ServiceError::ImageRejected(
+                "not a png or jpeg image".to_string(),
+            )),
+        }
+    }
+
+    fn format(&self) -> ImageFormat {
+        match self {
+            PhotoType::Png => ImageFormat::Png,
+            PhotoType::Jpeg => ImageFormat::Jpeg,
+        }
+    }
+}
+
+impl fmt::Display for PhotoType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PhotoType::Png => write!(f, "image/png"),
+            PhotoType::Jpeg => write!(f, "image/jpeg"),
+        }
+    }
+}
+
+// Every photo is stored as these variants, all jpeg, each fitting in a square of max_size pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PhotoVariant {
+    Thumbnail, // chat avatars and lists
+    Card,      // discovery cards
+    Full,
+}
+
+impl PhotoVariant {
+    pub const ALL: [PhotoVariant; 3] = [
+        PhotoVariant::Thumbnail,
+        PhotoVariant::Card,
+        PhotoVariant::Full,
+    ];
+
+    fn max_size(&self) -> u32 {
+        match self {
+            PhotoVariant::Thumbnail => PHOTO_THUMBNAIL_SIZE,
+            PhotoVariant::Card => PHOTO_CARD_SIZE,
+            PhotoVariant::Full => PHOTO_FULL_SIZE,
+        }
+    }
+
+    // The full variant keeps the photo uuid as key, where photos were stored before variants existed
+    pub fn key(&self, photo_uuid: &str) -> String {
+        match self {
+            PhotoVariant::Thumbnail => format!("{}_thumbnail", photo_uuid),
+            PhotoVariant::Card => format!("{}_card", photo_uuid),
+            PhotoVariant::Full => photo_uuid.to_string(),
+        }
+    }
+
+    // Photo uuid of an object key, the inverse of key
+    pub fn photo_uuid(key: &str) -> &str {
+        key.strip_suffix("_thumbnail")
+            .or_else(|| key.strip_suffix("_card"))
+            .unwrap_or(key)
+    }
+}
+
+// Decode the whole image, so that only well formed png and jpeg images are accepted.
+// Data after the end of the image is ignored : phones append some to their jpegs, and since the
+// uploaded file is never stored, only the variants rendered from its pixels, it can't be served.
+// The returned image is upright, its EXIF orientation is applied, and 8 bits rgb like the jpeg variants.
+pub fn decode_photo(data: &[u8]) -> Result<DynamicImage, ServiceError> {
+    let photo_type = PhotoType::sniff(data)?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(PHOTO_MAX_DIMENSION);
+    limits.max_image_height = Some(PHOTO_MAX_DIMENSION);
+    limits.max_alloc = Some(PHOTO_MAX_DECODED_SIZE);
+    let mut reader = ImageReader::with_format(Cursor::new(data), photo_type.format());
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(map_image_error)?;
+    // The header is checked before decoding anything, to refuse decompression bombs early
+    if decoder.total_bytes() > PHOTO_MAX_DECODED_SIZE {
+        return Err(ServiceError::ImageRejected(format!(
+            "image is larger than {} MB once decompressed",
+            PHOTO_MAX_DECODED_SIZE / 1024 / 1024
+        )));
+    }
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(map_image_error)?;
+    image.apply_orientation(orientation);
+
+    Ok(DynamicImage::ImageRgb8(image.into_rgb8()))
+}
+
+// Resize and re-encode the image into every variant, this is cpu heavy and should run off the async runtime.
+// The image comes from decode_photo : it is encoded as is when small enough, without any copy
+pub fn render_variants(image: &DynamicImage) -> Result<Vec<(PhotoVariant, Vec<u8>)>, ServiceError> {
+    let mut variants = Vec::new();
+    for variant in PhotoVariant::ALL {
+        let max_size = variant.max_size();
+        let resized = (image.width() > max_size || image.height() > max_size)
+            .then(|| image.resize(max_size, max_size, FilterType::Lanczos3));
+        let mut data = Vec::new();
+        resized
+            .as_ref()
+            .unwrap_or(image)
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut data, PHOTO_JPEG_QUALITY))
+            .map_err(|e| {
+                println!("photo variant encoding error : {:?}", e);
+                ServiceError::Internal
+            })?;
+        variants.push((variant, data));
+    }
+    Ok(variants)
+}
+
+fn map_image_error(error: ImageError) -> ServiceError {
+    let reason = match error {
+        ImageError::Limits(limit_error) => match limit_error.kind() {
+            LimitErrorKind::DimensionError => format!(
+                "image is wider or taller than {} pixels",
+                PHOTO_MAX_DIMENSION
+            ),
+            _ => format!(
+                "image is larger than {} MB once decompressed",
+                PHOTO_MAX_DECODED_SIZE / 1024 / 1024
+            ),
+        },
+        ImageError::Unsupported(_) => "image uses an unsupported encoding".to_string(),
+        _ => "image is corrupt".to_string(),
+    };
+    ServiceError::ImageRejected(reason)
+}
+
+// Compact placeholder shown by clients while the photo loads, see https://blurha.sh
+pub fn blurhash(image: &DynamicImage) -> Result<String, ServiceError> {
+    // The hash only keeps a few colours, a small image gives the same result much faster
+    let small = image
+        .thumbnail(PHOTO_BLURHASH_SIZE, PHOTO_BLURHASH_SIZE)
+        .to_rgba8();
+    let (components_x, components_y) = PHOTO_BLURHASH_COMPONENTS;
+    blurhash::encode(
+        components_x,
+        components_y,
+        small.width(),
+        small.height(),
+        small.as_raw(),
+    )
+    .map_err(|e| {
+        println!("blurhash encoding error : {:?}", e);
+        ServiceError::Internal
+    })
+}
+
+// Perceptual hash (dHash) : 64 bits telling whether each pixel of a tiny grayscale copy is brighter
+// than its right neighbour. Unlike a checksum it barely changes when the photo is resized,
+// recompressed or lightly edited, near duplicates are a few bits apart, see hash_distance
+pub fn perceptual_hash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x + 1, y)[0] > small.get_pixel(x, y)[0];
+            hash = (hash << 1) | brighter as u64;
+        }
+    }
+    hash
+}
+
+// Number of differing bits between two perceptual hashes
+pub fn hash_distance(hash1: u64, hash2: u64) -> u32 {
+    (hash1 ^ hash2).count_ones()
+}
+
+// Flat or evenly shaded photos have nearly all their bits equal, every such photo would be a near
+// duplicate of the others
+pub fn is_low_entropy(hash: u64) -> bool {
+    hash.count_ones().min(hash.count_zeros()) < PHOTO_HASH_MIN_BITS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::constants::PHOTO_DUPLICATE_DISTANCE;
+    use image::{Rgb, RgbImage};
+
+    // A colour gradient, detailed enough to survive the jpeg compression
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+        }))
+    }
+
+    // Waves in every direction, its perceptual hash has about as many set and unset bits
+    fn landscape(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x as f32 / width as f32, y as f32 / height as f32);
+            let value = 128. + 60. * (x * 17.).sin() + 60. * (y * 11. + x * 5.).cos();
+            Rgb([value as u8, (value * 0.8) as u8, 255 - value as u8])
+        }))
+    }
+
+    fn encode(image: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn data_after_the_image_is_decoded_but_never_rendered() {
+        let hidden = b"PK\x03\x04 a zip archive hidden after the image";
+        for format in [ImageFormat::Jpeg, ImageFormat::Png] {
+            let mut data = encode(&gradient(640, 480), format);
+            data.extend_from_slice(hidden);
+
+            let image = decode_photo(&data).unwrap();
+            assert_eq!((image.width(), image.height()), (640, 480));
+            for (_, variant) in render_variants(&image).unwrap() {
+                assert!(!contains(&variant, hidden));
+            }
+        }
+    }
+
+    // EXIF segment of a phone photo taken at 48°51'24" N : IFD0 points to a GPS IFD holding the latitude
+    fn gps_exif() -> Vec<u8> {
+        let mut tiff = b"II*\0\x08\0\0\0".to_vec();
+        // IFD0 at 8 : GPSInfo pointing to the GPS IFD at 26
+        tiff.extend_from_slice(&[1, 0, 0x25, 0x88, 4, 0, 1, 0, 0, 0, 26, 0, 0, 0, 0, 0, 0, 0]);
+        // GPS IFD at 26 : GPSLatitudeRef "N" inline, GPSLatitude 3 rationals at 56
+        tiff.extend_from_slice(&[2, 0, 1, 0, 2, 0, 2, 0, 0, 0, b'N', 0, 0, 0]);
+        tiff.extend_from_slice(&[2, 0, 5, 0, 3, 0, 0, 0, 56, 0, 0, 0, 0, 0, 0, 0]);
+        for value in [48u32, 1, 51, 1, 24, 1] {
+            tiff.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+        segment.extend_from_slice(b"Exif\0\0");
+        segment.extend_from_slice(&tiff);
+        segment
+    }
+
+    #[test]
+    fn gps_coordinates_are_not_rendered() {
+        let mut data = encode(&gradient(640, 480), ImageFormat::Jpeg);
+        let exif = gps_exif();
+        // The EXIF segment follows the start of image marker, like in camera jpegs
+        data.splice(2..2, exif.iter().copied());
+        let mut decoder = image::codecs::jpeg::JpegDecoder::new(Cursor::new(&data)).unwrap();
+        assert_eq!(decoder.exif_metadata().unwrap(), Some(exif[10..].to_vec()));
+
+        let image = decode_photo(&data).unwrap();
+        for (_, variant) in render_variants(&image).unwrap() {
+            assert!(!contains(&variant, b"Exif"));
+            assert!(!contains(&variant, &exif[10..]));
+            let mut decoder = image::codecs::jpeg::JpegDecoder::new(Cursor::new(&variant)).unwrap();
+            assert_eq!(decoder.exif_metadata().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn small_images_are_rendered_without_resizing() {
+        let rgba = DynamicImage::ImageRgba8(gradient(200, 100).to_rgba8());
+        let image = decode_photo(&encode(&rgba, ImageFormat::Png)).unwrap();
+        assert!(matches!(image, DynamicImage::ImageRgb8(_)));
+
+        for (variant, data) in render_variants(&image).unwrap() {
+            let rendered = image::load_from_memory(&data).unwrap();
+            let max_size = variant.max_size().min(200);
+            assert!(rendered.width() <= max_size && rendered.height() <= max_size);
+            if variant.max_size() >= 200 {
+                assert_eq!((rendered.width(), rendered.height()), (200, 100));
+            }
+        }
+    }
+
+    #[test]
+    fn flat_photos_have_low_entropy_hashes() {
+        let flat =
+            |colour: [u8; 3]| DynamicImage::ImageRgb8(RgbImage::from_pixel(640, 480, Rgb(colour)));
+        let vertical = DynamicImage::ImageRgb8(RgbImage::from_fn(640, 480, |_, y| {
+            Rgb([(y * 255 / 480) as u8, 0, 0])
+        }));
+        for image in [
+            flat([255, 255, 255]),
+            flat([40, 40, 40]),
+            gradient(640, 480),
+            vertical,
+        ] {
+            let photo = decode_photo(&encode(&image, ImageFormat::Jpeg)).unwrap();
+            assert!(is_low_entropy(perceptual_hash(&photo)));
+        }
+    }
+
+    #[test]
+    fn recompressed_photos_are_near_duplicates() {
+        let original = landscape(1200, 900);
+        let hash = perceptual_hash(&original);
+        assert!(!is_low_entropy(hash));
+
+        let resized = original.resize(600, 450, FilterType::Triangle);
+        let mut data = Vec::new();
+        resized
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut data, 40))
+            .unwrap();
+        let copy = decode_photo(&data).unwrap();
+        assert!(hash_distance(hash, perceptual_hash(&copy)) <= PHOTO_DUPLICATE_DISTANCE);
+
+        let mirrored = original.fliph();
+        assert!(hash_distance(hash, perceptual_hash(&mirrored)) > PHOTO_DUPLICATE_DISTANCE);
+    }
+
+    #[test]
+    fn only_png_and_jpeg_images_are_accepted() {
+        let gif = b"GIF89a\x01\x00\x01\x00\x00\x00\x00;";
+        assert!(matches!(
+            decode_photo(gif),
+            Err(ServiceError::ImageRejected(_))
+        ));
+
+        let mut truncated = encode(&gradient(640, 480), ImageFormat::Png);
+        truncated.truncate(truncated.len() / 2);
+        assert!(matches!(
+            decode_photo(&truncated),
+            Err(ServiceError::ImageRejected(_))
+        ));
+    }
+}
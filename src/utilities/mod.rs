@@ -1 +1,7 @@
+pub mod api_version;
+pub mod geo;
+pub mod images;
 pub mod responses;
+#[cfg(test)]
+pub mod test_fixtures;
+pub mod validation;
@@ -0,0 +1,100 @@
+// Test only : application states over a fresh database and helpers to seed it
+use crate::configs::app_state::AppState;
+use crate::configs::config::{Config, StorageBackend};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OpenFlags};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// The dev config with photos kept in memory, over an empty database built from databaseCreation.sql.
+// The dev config is also used by release builds, for benchmarks
+pub async fn app_state() -> Arc<AppState> {
+    app_state_with(|_| ()).await
+}
+
+pub async fn app_state_with(configure: impl FnOnce(&mut Config)) -> Arc<AppState> {
+    let mut config: Config = toml::from_str(include_str!("../configs/dev.toml")).unwrap();
+    config.storage.backend = StorageBackend::Memory;
+    configure(&mut config);
+    // A named in memory database is shared by the connections of the pool, and dropped with them
+    let manager =
+        SqliteConnectionManager::file(format!("file:{}?mode=memory&cache=shared", Uuid::now_v7()))
+            .with_flags(
+                OpenFlags::SQLITE_OPEN_READ_WRITE
+                    | OpenFlags::SQLITE_OPEN_CREATE
+                    | OpenFlags::SQLITE_OPEN_URI,
+            );
+    let state = AppState::with_database(&config, manager).await;
+    state
+        .connection
+        .get()
+        .unwrap()
+        .execute_batch(include_str!("../../databaseCreation.sql"))
+        .expect("databaseCreation.sql failed");
+    state
+}
+
+pub struct UserFixture {
+    pub name: &'static str,
+    pub birthdate: &'static str,
+    pub latitude: f32, // degrees
+    pub longitude: f32,
+    pub gender: &'static str,
+    pub looking_for: &'static str,
+    pub search_radius: u16,
+    pub age_min: u8,
+    pub age_max: u8,
+}
+
+// Paris, 36 years old woman looking for men of any age within 50 km
+impl Default for UserFixture {
+    fn default() -> UserFixture {
+        UserFixture {
+            name: "Ann",
+            birthdate: "1990-02-14",
+            latitude: 48.8566,
+            longitude: 2.3522,
+            gender: "female",
+            looking_for: "male",
+            search_radius: 50,
+            age_min: 18,
+            age_max: 127,
+        }
+    }
+}
+
+// Returns the uuid of the created user
+pub fn insert_user(state: &Arc<AppState>, user: UserFixture) -> String {
+    let user_uuid = Uuid::now_v7().to_string();
+    state
+        .connection
+        .get()
+        .unwrap()
+        .execute(
+            "INSERT INTO Users (user_uuid, private_user_uuid, name, password, email, last_seen, birthdate,
+                latitude, longitude, gender, looking_for, search_radius, looking_for_age_min, looking_for_age_max)
+                VALUES (?, ?, ?, '', ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                user_uuid,
+                Uuid::now_v7().to_string(),
+                user.name,
+                format!("{}@{}.test", user.name, user_uuid),
+                format!("{:?}", chrono::offset::Utc::now()),
+                user.birthdate,
+                user.latitude.to_radians(),
+                user.longitude.to_radians(),
+                user.gender,
+                user.looking_for,
+                user.search_radius,
+                user.age_min,
+                user.age_max
+            ],
+        )
+        .unwrap();
+    user_uuid
+}
+
+// Point `km` kilometers east of Paris, at the latitude of UserFixture::default
+pub fn paris_east(km: f32) -> f32 {
+    2.3522 + (km / (6371. * 48.8566_f32.to_radians().cos())).to_degrees()
+}
@@ -0,0 +1,40 @@
+use crate::my_errors::service_errors::ServiceError;
+
+// Values must come from `allowed`, at most `max` of them and without duplicates
+pub fn check_choices(
+    values: &[String],
+    allowed: &[&str],
+    max: usize,
+    field: &str,
+) -> Result<(), ServiceError> {
+    if values.len() > max {
+        return Err(ServiceError::ValueNotAccepted(
+            values.join(","),
+            format!("at most {max} {field} can be chosen"),
+        ));
+    }
+    for (i, value) in values.iter().enumerate() {
+        if !allowed.contains(&value.as_str()) {
+            return Err(ServiceError::ValueNotAccepted(
+                value.to_string(),
+                format!("unknown value for {field}"),
+            ));
+        }
+        if values[..i].contains(value) {
+            return Err(ServiceError::ValueNotAccepted(
+                value.to_string(),
+                format!("duplicated value for {field}"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Rows to skip before the page, it must also fit in the i64 sqlite expects
+pub fn page_offset(page: usize, page_size: usize) -> Result<usize, ServiceError> {
+    page.checked_mul(page_size)
+        .filter(|offset| i64::try_from(*offset).is_ok())
+        .ok_or_else(|| {
+            ServiceError::ValueNotAccepted(page.to_string(), "page is too large".to_string())
+        })
+}